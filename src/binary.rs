@@ -0,0 +1,3134 @@
+//! Locating and installing the Grafana MCP server binary.
+
+use std::{
+    cell::OnceCell,
+    collections::{HashMap, HashSet},
+    fs, iter,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use zed_extension_api::{self as zed, Result};
+
+use crate::{
+    credentials::Secret,
+    debug_log,
+    dev::Checkout,
+    environment::Environment,
+    integrity::{self, Signature},
+    lock::InstallLock,
+    log,
+    manifest::{self, Check, Manifest},
+    paths,
+    release_cache::{self, CachedRelease},
+    releases::{self, Repo},
+    retry,
+    settings::GrafanaContextServerSettings,
+    status::{LogReporter, Reporter, Status},
+    subprocess, tool_names,
+};
+
+const REPO_NAME: &str = "grafana/mcp-grafana";
+const BINARY_NAME: &str = "mcp-grafana";
+
+/// The Go package of the server, for building it from source.
+const GO_PACKAGE: &str = "github.com/grafana/mcp-grafana/cmd/mcp-grafana";
+
+/// How long reading the CPU type with `sysctl` may take.
+const SYSCTL_TIMEOUT_SECS: u64 = 5;
+
+/// The names of the server's release assets, as rendered by
+/// [`AssetName::render`].
+const ASSET_NAME_TEMPLATE: &str = "mcp-grafana_{os}_{arch}.{ext}";
+
+/// The suffix of the names of assets built for musl libc, as in
+/// `mcp-grafana_Linux_x86_64_musl.tar.gz`.
+const MUSL_SUFFIX: &str = "_musl";
+
+/// The names of the server's release assets built for musl libc.
+const MUSL_ASSET_NAME_TEMPLATE: &str = "mcp-grafana_{os}_{arch}_musl.{ext}";
+
+/// How long looking for musl's dynamic linker may take.
+const LIBC_DETECTION_TIMEOUT_SECS: u64 = 5;
+
+/// How long building the server from source may take.
+const GO_BUILD_TIMEOUT_SECS: u64 = 600;
+
+/// The file marking a version directory as pinned by `server_version`,
+/// which keeps it from being cleaned up when another version is installed.
+const PINNED_MARKER: &str = ".pinned";
+
+/// The oldest release of the server which understands the flags the
+/// extension passes to it.
+const MIN_SUPPORTED_VERSION: Version = Version::new(0, 2, 0);
+
+/// The file recording that `purge_on_next_start` was acted on, so that the
+/// installed servers are purged once rather than on every start.
+const PURGED_MARKER: &str = ".purged";
+
+/// The file recording that `force_reinstall` was acted on, so that the
+/// release is downloaded again once rather than on every start.
+const REINSTALLED_MARKER: &str = ".reinstalled";
+
+/// The prefix of the files recording that the releases installed before
+/// they were kept apart by platform were moved, once per platform; see
+/// [`migrate_legacy_dirs`].
+const MIGRATED_PREFIX: &str = ".migrated-";
+
+/// The prefix of the files marking a release whose corrupted install was
+/// removed to be installed again.
+const RECOVERED_PREFIX: &str = ".recovered-";
+
+/// How long the server may take to print its version.
+const VERIFY_TIMEOUT_SECS: u64 = 10;
+
+/// How many releases before the latest one to look through for an asset
+/// for the current platform.
+const MAX_FALLBACK_RELEASES: usize = 5;
+
+/// The prefix of the directories releases are installed into before being
+/// moved into place.
+const STAGING_PREFIX: &str = ".staging-";
+
+/// How old a staging directory must be before it is considered left over
+/// from an interrupted install.
+const STALE_STAGING_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// How many directories deep a binary may be nested in a release archive.
+const MAX_ARCHIVE_DEPTH: usize = 3;
+
+/// How long extracting a downloaded archive may take.
+const EXTRACT_TIMEOUT_SECS: u64 = 120;
+
+/// The directory of the extension's working directory which nightly builds
+/// are installed in, apart from releases.
+const NIGHTLY_DIR: &str = "nightly";
+
+/// The directory of the extension's working directory which releases of
+/// repositories set by `github_repo` are installed in, apart from the
+/// server's.
+const REPOS_DIR: &str = "repos";
+
+/// The tags of nightly builds unless `nightly_tag_pattern` is set.
+const DEFAULT_NIGHTLY_TAG_PATTERN: &str = "nightly*";
+
+/// The longest time to go without looking for a new nightly build.
+const NIGHTLY_CHECK_INTERVAL_SECS: u64 = 60 * 60;
+
+/// The inputs which determine which server binary is used.
+///
+/// A cached binary path is only reused while these stay the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BinaryOptions {
+    platform: zed::Os,
+    arch: zed::Architecture,
+    /// Why the server is installed for `arch`.
+    arch_reason: ArchReason,
+    /// The directory releases are installed in instead of the extension's
+    /// working directory, if set.
+    storage_dir: Option<String>,
+    /// The directory releases for `platform` and `arch` are installed in,
+    /// within `storage_dir`.
+    platform_dir: String,
+    /// The C library of the system, on Linux.
+    libc: Option<Libc>,
+    version: ServerVersion,
+    /// An existing server binary to use instead of downloading one.
+    binary_path: Option<String>,
+    /// A local release archive to install instead of downloading one.
+    archive_path: Option<String>,
+    /// The version of the server in `archive_path`.
+    archive_version: Option<String>,
+    /// A local checkout to build the server from instead of downloading it.
+    dev: Option<Checkout>,
+    /// Whether to use a server found on `PATH` before downloading one.
+    prefer_system_binary: bool,
+    /// Whether to build the server with `go install` instead of
+    /// downloading it.
+    build_from_source: bool,
+    /// Which releases are tracked.
+    channel: Channel,
+    /// The repository releases are installed from.
+    repo: Repo,
+    /// The name of the server binary in release archives, without `.exe`.
+    binary_name: String,
+    /// The template of the name of the release asset for the platform,
+    /// used instead of the server's names.
+    asset_name_template: Option<String>,
+    /// The pattern of the tags of nightly builds, on the nightly channel.
+    nightly_tag_pattern: String,
+    /// Whether pre-releases may be installed without naming them.
+    allow_prerelease: bool,
+    /// Whether to look for new releases when one is already installed.
+    auto_update: bool,
+    /// The token to authenticate to GitHub's API with.
+    github_token: Option<Secret>,
+    /// The template of the URLs to download assets from instead of GitHub.
+    download_url_template: Option<String>,
+    /// How long to reuse the result of the latest-release lookup, in
+    /// seconds.
+    release_check_interval_seconds: u64,
+    /// How many installed releases to keep when installing another.
+    keep_versions: usize,
+    /// Whether to remove all installed releases once, before installing.
+    purge_on_next_start: bool,
+    /// Whether to download the selected release again once, even if it is
+    /// installed.
+    force_reinstall: bool,
+    /// Whether to log that a newer release exists when one is held back.
+    notify_new_releases: bool,
+    /// Whether to fail rather than fall back to an earlier release when the
+    /// latest one has no asset for the platform.
+    strict_latest: bool,
+    /// Whether to check downloads against the release's checksums.
+    verify_checksum: bool,
+    /// How to verify the signature of the release's checksums, if at all.
+    signature: Option<Signature>,
+}
+
+/// An architecture to install the server for instead of the detected one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub(crate) enum Arch {
+    #[serde(rename = "arm64")]
+    Arm64,
+    #[serde(rename = "x86_64")]
+    X8664,
+    #[serde(rename = "i386")]
+    I386,
+}
+
+impl From<Arch> for zed::Architecture {
+    fn from(arch: Arch) -> Self {
+        match arch {
+            Arch::Arm64 => Self::Aarch64,
+            Arch::X8664 => Self::X8664,
+            Arch::I386 => Self::X86,
+        }
+    }
+}
+
+/// Why the server is installed for an architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchReason {
+    /// It is the architecture Zed reports.
+    Detected,
+    /// The `arch` setting names it.
+    Setting,
+    /// Zed runs as x86_64 under Rosetta on Apple Silicon, where the
+    /// native arm64 build avoids translating the server too.
+    Rosetta,
+}
+
+/// Returns the architecture to install the server for on `platform`,
+/// and why.
+///
+/// `arch` overrides the `detected` one. Otherwise an x86_64 Zed on a Mac
+/// for which `is_apple_silicon` returns true is running under Rosetta, and
+/// the arm64 build is chosen unless `prefer_native` is false.
+fn select_arch(
+    platform: zed::Os,
+    detected: zed::Architecture,
+    arch: Option<Arch>,
+    prefer_native: bool,
+    is_apple_silicon: impl FnOnce() -> bool,
+) -> (zed::Architecture, ArchReason) {
+    if let Some(arch) = arch {
+        return (arch.into(), ArchReason::Setting);
+    }
+    if platform == zed::Os::Mac
+        && detected == zed::Architecture::X8664
+        && prefer_native
+        && is_apple_silicon()
+    {
+        return (zed::Architecture::Aarch64, ArchReason::Rosetta);
+    }
+    (detected, ArchReason::Detected)
+}
+
+/// Returns whether this Mac has an Apple Silicon CPU, even if Zed runs
+/// under Rosetta.
+fn is_apple_silicon() -> bool {
+    let argv = vec!["sysctl".into(), "-n".into(), "hw.optional.arm64".into()];
+    // Intel Macs don't have this sysctl at all.
+    subprocess::run("sysctl", argv, SYSCTL_TIMEOUT_SECS)
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+}
+
+/// The C library of a Linux system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Libc {
+    Gnu,
+    Musl,
+}
+
+/// Returns the C library of `platform`, which is `libc` if set, or as
+/// returned by `detect` on Linux.
+fn select_libc(
+    platform: zed::Os,
+    libc: Option<Libc>,
+    detect: impl FnOnce() -> Libc,
+) -> Option<Libc> {
+    match platform {
+        zed::Os::Linux => Some(libc.unwrap_or_else(detect)),
+        zed::Os::Mac | zed::Os::Windows => None,
+    }
+}
+
+/// Returns whether this Linux system uses musl libc, which Alpine Linux and
+/// its containers do, by looking for musl's dynamic linker.
+fn detect_libc() -> Libc {
+    let argv = vec![
+        "sh".into(),
+        "-c".into(),
+        r#"for linker in /lib/ld-musl-*; do [ -e "$linker" ] && exit 0; done; exit 1"#.into(),
+    ];
+    match subprocess::run("libc detection", argv, LIBC_DETECTION_TIMEOUT_SECS) {
+        Ok(_) => Libc::Musl,
+        Err(_) => Libc::Gnu,
+    }
+}
+
+/// The releases of the server to track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Channel {
+    /// Stable releases.
+    #[default]
+    Stable,
+    /// Pre-releases too, as with `allow_prerelease`.
+    Prerelease,
+    /// The builds published as releases of `nightly_repo` tagged like
+    /// `nightly_tag_pattern`.
+    Nightly,
+}
+
+/// Which release of the server to install.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ServerVersion {
+    /// The latest release.
+    Latest,
+    /// The release with this tag.
+    Tag(String),
+    /// The newest release satisfying this range.
+    Range(VersionReq),
+}
+
+impl BinaryOptions {
+    /// Returns the options given by `settings`, for the platform Zed is
+    /// running on, as detected by `is_apple_silicon` and `detect_libc`
+    /// where needed.
+    fn new(
+        settings: &GrafanaContextServerSettings,
+        process_env: &Environment,
+        is_apple_silicon: impl FnOnce() -> bool,
+        detect_libc: impl FnOnce() -> Libc,
+    ) -> Result<Self> {
+        let (platform, detected_arch) = zed::current_platform();
+        let (arch, arch_reason) = select_arch(
+            platform,
+            detected_arch,
+            settings.arch,
+            settings.prefer_native_arch,
+            is_apple_silicon,
+        );
+        let version = match settings.server_version.as_deref() {
+            None => ServerVersion::Latest,
+            Some(_) if settings.channel == Channel::Nightly => {
+                return Err(
+                    "`server_version` can't be combined with `\"channel\": \"nightly\"`, which \
+                     always installs the newest nightly build"
+                        .into(),
+                );
+            }
+            Some(version) => parse_version(version)?,
+        };
+        let nightly = settings.channel == Channel::Nightly;
+        let api_base = settings.github_api_base.as_deref();
+        let github_repo = settings.github_repo.as_deref().unwrap_or(REPO_NAME);
+        let repo = match &settings.nightly_repo {
+            Some(nightly_repo) if nightly => Repo::new("nightly_repo", nightly_repo, api_base)?,
+            _ => Repo::new("github_repo", github_repo, api_base)?,
+        };
+        let binary_name = settings
+            .binary_name
+            .as_deref()
+            .unwrap_or(BINARY_NAME)
+            .trim();
+        if binary_name.is_empty() || binary_name.contains(['/', '\\']) {
+            return Err(format!(
+                "invalid `binary_name` '{binary_name}'; expected a file name such as 'mcp-grafana'"
+            ));
+        }
+        let storage_dir = storage_dir(settings)?;
+        let mut release_check_interval_seconds = settings.release_check_interval_seconds;
+        let mut platform_dir = platform_dir(platform, arch);
+        if github_repo.trim() != REPO_NAME || api_base.is_some() {
+            // Releases of forks are kept apart, since they may reuse the
+            // server's tags.
+            platform_dir = format!(
+                "{REPOS_DIR}/{}/{platform_dir}",
+                github_repo.trim().replace('/', "-")
+            );
+        }
+        if nightly {
+            release_check_interval_seconds =
+                release_check_interval_seconds.min(NIGHTLY_CHECK_INTERVAL_SECS);
+            // Nightly builds are kept apart so that they are never mistaken
+            // for releases, and the releases are still there to go back to.
+            platform_dir = format!("{NIGHTLY_DIR}/{}", self::platform_dir(platform, arch));
+        }
+        let platform_dir = in_storage(storage_dir.as_deref(), &platform_dir);
+        let signature = Signature::new(settings)?;
+        if signature.is_some() && settings.skip_checksum_verification {
+            return Err(
+                "`verify_signature` can't be combined with `skip_checksum_verification`, since \
+                 the signature covers the release's checksums"
+                    .into(),
+            );
+        }
+        let binary_path = settings
+            .binary_path
+            .as_deref()
+            .map(|path| paths::expand_home("binary_path", path))
+            .transpose()?;
+        Ok(Self {
+            platform,
+            arch,
+            arch_reason,
+            storage_dir,
+            platform_dir,
+            libc: select_libc(platform, settings.libc, detect_libc),
+            version,
+            binary_path,
+            archive_path: settings
+                .archive_path
+                .as_deref()
+                .map(|path| paths::expand_home("archive_path", path))
+                .transpose()?,
+            archive_version: settings.archive_version.clone(),
+            dev: Checkout::new(settings)?,
+            prefer_system_binary: settings.prefer_system_binary,
+            build_from_source: settings.build_from_source,
+            channel: settings.channel,
+            repo,
+            binary_name: binary_name.into(),
+            asset_name_template: settings
+                .asset_name_template
+                .as_deref()
+                .map(|template| {
+                    check_asset_name_template(template).map(|()| template.trim().to_string())
+                })
+                .transpose()?,
+            nightly_tag_pattern: settings
+                .nightly_tag_pattern
+                .clone()
+                .unwrap_or_else(|| DEFAULT_NIGHTLY_TAG_PATTERN.into()),
+            allow_prerelease: settings.allow_prerelease || settings.channel != Channel::Stable,
+            auto_update: settings.auto_update,
+            github_token: settings.github_token.clone().or_else(|| {
+                ["GITHUB_TOKEN", "GH_TOKEN"]
+                    .iter()
+                    .find_map(|name| process_env.var(name))
+                    .map(|token| Secret::from(token.to_string()))
+            }),
+            download_url_template: download_url_template(settings)?,
+            release_check_interval_seconds,
+            keep_versions: settings.keep_versions,
+            purge_on_next_start: settings.purge_on_next_start,
+            force_reinstall: settings.force_reinstall,
+            notify_new_releases: settings.notify_new_releases,
+            strict_latest: settings.strict_latest,
+            verify_checksum: !settings.skip_checksum_verification,
+            signature,
+        })
+    }
+}
+
+/// Parses `server_version` as either a release tag, such as `v0.2.5`, or
+/// a semver range, such as `0.2.x` or `>=0.2, <0.3`.
+///
+/// Full versions are exact pins, and the `v` prefix of the server's tags
+/// is added if it is missing.
+fn parse_version(version: &str) -> Result<ServerVersion> {
+    let version = version.trim();
+    if releases::parse_tag(version).is_none()
+        && let Ok(req) = VersionReq::parse(version.strip_prefix('v').unwrap_or(version))
+    {
+        return Ok(ServerVersion::Range(req));
+    }
+    if version.is_empty()
+        || !version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+    {
+        return Err(format!(
+            "invalid `server_version` '{version}'; expected a release tag of {REPO_NAME} \
+             such as 'v0.2.5', or a range such as '0.2.x'"
+        ));
+    }
+    let tag = if version.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("v{version}")
+    } else {
+        version.into()
+    };
+    if let Some(version) = releases::parse_tag(&tag) {
+        check_supported(&version, "upgrade or remove the pin")?;
+    }
+    Ok(ServerVersion::Tag(tag))
+}
+
+/// Returns an error if release `version` of the server is older than
+/// [`MIN_SUPPORTED_VERSION`], suggesting to `fix` it.
+fn check_supported(version: &Version, fix: &str) -> Result<()> {
+    if *version >= MIN_SUPPORTED_VERSION {
+        return Ok(());
+    }
+    Err(format!(
+        "{BINARY_NAME} {version} is older than the minimum supported {MIN_SUPPORTED_VERSION}; \
+         {fix}"
+    ))
+}
+
+/// The most recently resolved server binary of each context server, along
+/// with the options it was resolved for.
+#[derive(Debug, Default)]
+pub(crate) struct BinaryCache {
+    /// The entries by context server id, since each context server may
+    /// pin a different release.
+    entries: HashMap<String, (BinaryOptions, String)>,
+    /// The `binary_path`s and servers on `PATH` whose version was checked.
+    checked: HashSet<String>,
+    /// Whether a newer release of a server held back by a pin was looked
+    /// for, which happens once per session.
+    notified_of_newer_release: bool,
+    /// Whether this Mac has an Apple Silicon CPU, once detected.
+    apple_silicon: Option<bool>,
+    /// The C library of this Linux system, once detected.
+    libc: Option<Libc>,
+}
+
+impl BinaryCache {
+    /// Returns the [`BinaryOptions`] given by `settings`.
+    ///
+    /// Whether the Mac has an Apple Silicon CPU and the machine's C library
+    /// are detected once per session, rather than on every start.
+    pub(crate) fn options(
+        &mut self,
+        settings: &GrafanaContextServerSettings,
+        process_env: &Environment,
+    ) -> Result<BinaryOptions> {
+        BinaryOptions::new(
+            settings,
+            process_env,
+            || *self.apple_silicon.get_or_insert_with(is_apple_silicon),
+            || *self.libc.get_or_insert_with(detect_libc),
+        )
+    }
+
+    /// Returns the path of the server binary for `options` of the context
+    /// server `context_server_id`, installing it if needed.
+    ///
+    /// The cached path is reused without contacting GitHub as long as the
+    /// context server's options haven't changed and the binary still has
+    /// the size it was installed with. A configured `binary_path` is used
+    /// as is, and so is a server found on `PATH` if `prefer_system_binary`
+    /// is set; neither downloads anything.
+    pub(crate) fn get(
+        &mut self,
+        context_server_id: &str,
+        options: &BinaryOptions,
+    ) -> Result<String> {
+        if !options.purge_on_next_start {
+            fs::remove_file(PURGED_MARKER).ok();
+        } else if fs::metadata(PURGED_MARKER).is_err() {
+            purge(options);
+            self.entries.clear();
+            fs::write(PURGED_MARKER, "")
+                .map_err(|err| format!("failed to write '{PURGED_MARKER}': {err}"))?;
+        }
+        if let Some(dev) = &options.dev {
+            let binary_path = dev.binary(options.platform)?;
+            set_active(context_server_id, options, None);
+            return Ok(binary_path);
+        }
+        if let Some(path) = &options.binary_path {
+            paths::check_executable_file("binary_path", path)?;
+            self.check_external(path, "upgrade it or remove `binary_path`")?;
+            set_active(context_server_id, options, None);
+            return Ok(path.clone());
+        }
+        migrate_legacy_dirs(options);
+        if let Some(archive_path) = &options.archive_path {
+            paths::check_readable_file("archive_path", archive_path)?;
+            let binary_path = install_archive(archive_path, options)?;
+            set_active(context_server_id, options, Some(&binary_path));
+            return Ok(binary_path);
+        }
+        if options.prefer_system_binary {
+            if let Some(path) = subprocess::find_on_path(&options.binary_name, options.platform) {
+                debug_log(format!(
+                    "using the Grafana MCP server at '{path}' found on PATH, since \
+                     `prefer_system_binary` is set"
+                ));
+                self.check_external(&path, "upgrade it or unset `prefer_system_binary`")?;
+                set_active(context_server_id, options, None);
+                return Ok(path);
+            }
+            log(format!(
+                "`prefer_system_binary` is set, but {} isn't on PATH; downloading it",
+                options.binary_name
+            ));
+        }
+        let reinstall = if !options.force_reinstall {
+            fs::remove_file(REINSTALLED_MARKER).ok();
+            false
+        } else {
+            fs::metadata(REINSTALLED_MARKER).is_err()
+        };
+        if let Some((cached_options, path)) = self.entries.get(context_server_id)
+            && !reinstall
+            && cached_options == options
+            && let Some((version_dir, _)) = path.rsplit_once('/')
+            && load_manifest(options).check(version_dir, path, false) == Check::Intact
+        {
+            return Ok(path.clone());
+        }
+
+        // Another window may install the release while this one waits for
+        // the lock, which `install` then finds installed.
+        let binary_path = InstallLock::acquire()
+            .and_then(|_lock| install(context_server_id, options, reinstall, &GitHub, &LogReporter))
+            .inspect_err(|err| {
+                LogReporter.report(Status::Failed { error: err.clone() });
+            })?;
+        if reinstall {
+            fs::write(REINSTALLED_MARKER, "")
+                .map_err(|err| format!("failed to write '{REINSTALLED_MARKER}': {err}"))?;
+        }
+        set_active(context_server_id, options, Some(&binary_path));
+        self.entries.insert(
+            context_server_id.into(),
+            (options.clone(), binary_path.clone()),
+        );
+        // Nightly builds aren't compared with releases.
+        let held_back = options.channel != Channel::Nightly
+            && (options.version != ServerVersion::Latest || !options.auto_update);
+        if held_back && options.notify_new_releases && !self.notified_of_newer_release {
+            log_newer_release(options, &binary_path);
+            self.notified_of_newer_release = true;
+        }
+        Ok(binary_path)
+    }
+
+    /// Checks that the server at `path`, which wasn't installed by the
+    /// extension, is at least [`MIN_SUPPORTED_VERSION`], once per path.
+    ///
+    /// Servers whose version can't be read are assumed to be supported.
+    fn check_external(&mut self, path: &str, fix: &str) -> Result<()> {
+        if self.checked.contains(path) {
+            return Ok(());
+        }
+        match reported_version(path) {
+            Ok(Some(version)) => check_supported(&version, fix)
+                .map_err(|err| format!("the Grafana MCP server at '{path}' is too old: {err}"))?,
+            Ok(None) => {}
+            Err(err) => log(format!(
+                "couldn't check the version of the Grafana MCP server at '{path}': {err}"
+            )),
+        }
+        self.checked.insert(path.into());
+        Ok(())
+    }
+}
+
+/// Installs the release of the server selected by `options`, unless it is
+/// already installed, and returns the path of its binary.
+///
+/// A pinned release which is already installed is used without contacting
+/// GitHub, and so is the newest installed release if GitHub can't be
+/// reached or `auto_update` is off. If the latest release isn't ready, as
+/// [`unready_reason`] tells, the newest earlier release which is ready is
+/// installed instead, unless `strict_latest` is set.
+///
+/// If `reinstall` is set, installed releases are ignored, and the selected
+/// one is downloaded again, replacing its directory once the download is
+/// verified.
+///
+/// Releases are looked up and downloaded from `source`, and the phases of
+/// installing one are reported to `reporter`.
+///
+/// Callers hold the [`InstallLock`], since this also removes old releases.
+fn install(
+    context_server_id: &str,
+    options: &BinaryOptions,
+    reinstall: bool,
+    source: &dyn Source,
+    reporter: &dyn Reporter,
+) -> Result<String> {
+    let repo = &options.repo;
+    let find_installed = |version_dir: &str| {
+        if reinstall {
+            return Ok(None);
+        }
+        installed_binary(version_dir, options)
+    };
+    // Identifies the platform's asset in the persisted release lookup.
+    let asset_name = asset_key(options);
+
+    let has_asset = |release: &zed::GithubRelease| find_asset(release, options).is_some();
+    let go = OnceCell::new();
+    let find_go = || {
+        go.get_or_init(|| subprocess::find_on_path("go", options.platform))
+            .clone()
+    };
+
+    // Uses the newest installed release satisfying `req` if `auto_update`
+    // is off, or if it was installed recently enough that a newer one is
+    // unlikely, which also works when the release lookup wasn't persisted.
+    let max_age = Duration::from_secs(options.release_check_interval_seconds);
+    let reuse_installed = |req: Option<&VersionReq>| {
+        if reinstall {
+            return None;
+        }
+        let (_, binary_path) = newest_installed(options, req)?;
+        let fresh = || {
+            binary_path
+                .rsplit_once('/')
+                .and_then(|(version_dir, _)| load_manifest(options).age(version_dir))
+                .is_some_and(|age| age < max_age)
+        };
+        (!options.auto_update || fresh()).then_some(binary_path)
+    };
+
+    let release = match &options.version {
+        ServerVersion::Latest if options.channel == Channel::Nightly => {
+            if !options.auto_update
+                && !reinstall
+                && let Some((_, binary_path)) = newest_nightly(options)
+            {
+                return Ok(binary_path);
+            }
+            if let Some(cached) = release_cache::load(
+                &asset_name,
+                &repo.to_string(),
+                options.channel,
+                options.allow_prerelease,
+                max_age,
+            ) && let Some(binary_path) =
+                find_installed(&version_dir(&options.platform_dir, &cached.tag))?
+            {
+                return Ok(binary_path);
+            }
+            reporter.report(Status::CheckingForRelease);
+            let releases = match source.list(repo, options.github_token.as_ref()) {
+                Ok(releases) => releases,
+                Err(err) => {
+                    let Some((tag, binary_path)) = newest_nightly(options) else {
+                        return Err(format!(
+                            "{err}; no nightly build of the server is installed to fall back to"
+                        ));
+                    };
+                    log(format!(
+                        "running offline on the installed nightly build {tag} of the server, \
+                         since looking up releases failed: {err}"
+                    ));
+                    return Ok(binary_path);
+                }
+            };
+            let pattern = &options.nightly_tag_pattern;
+            releases
+                .into_iter()
+                .find(|release| {
+                    tool_names::glob_matches(pattern, &release.version) && has_asset(release)
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "no release of {repo} tagged like '{pattern}' has an asset for {}",
+                        platform_description(options)
+                    )
+                })?
+        }
+        ServerVersion::Latest => {
+            if let Some(binary_path) = reuse_installed(None) {
+                return Ok(binary_path);
+            }
+            if let Some(cached) = release_cache::load(
+                &asset_name,
+                &repo.to_string(),
+                options.channel,
+                options.allow_prerelease,
+                max_age,
+            ) && let Some(binary_path) =
+                find_installed(&version_dir(&options.platform_dir, &cached.tag))?
+            {
+                return Ok(binary_path);
+            }
+            reporter.report(Status::CheckingForRelease);
+            let latest = source.latest(
+                repo,
+                options.allow_prerelease,
+                options.github_token.as_ref(),
+            );
+            let latest = match latest {
+                Ok(latest) => latest,
+                Err(err) => return use_installed(options, None, err),
+            };
+            match unready_reason(&latest, options) {
+                None => latest,
+                Some(_) if options.strict_latest || options.build_from_source => latest,
+                Some(_)
+                    if !has_asset(&latest)
+                        && options.asset_name_template.is_none()
+                        && find_go().is_some() =>
+                {
+                    log(format!(
+                        "release {} of {repo} has no asset for {}; building it from source \
+                         with go instead",
+                        latest.version,
+                        platform_description(options)
+                    ));
+                    latest
+                }
+                Some(reason) => fall_back_from(latest, &reason, options, source)?,
+            }
+        }
+        ServerVersion::Range(req) => {
+            if let Some(binary_path) = reuse_installed(Some(req)) {
+                return Ok(binary_path);
+            }
+            reporter.report(Status::CheckingForRelease);
+            let releases = match source.list(repo, options.github_token.as_ref()) {
+                Ok(releases) => releases,
+                Err(err) => return use_installed(options, Some(req), err),
+            };
+            let tags = releases
+                .iter()
+                .filter(|release| options.build_from_source || has_asset(release))
+                .map(|release| release.version.as_str());
+            let tag = releases::newest_matching(req, tags, options.allow_prerelease)
+                .ok_or_else(|| format!("no release of {repo} satisfies `server_version` '{req}'"))?
+                .to_string();
+            releases
+                .into_iter()
+                .find(|release| release.version == tag)
+                .expect("the selected tag is one of the releases")
+        }
+        ServerVersion::Tag(tag) => {
+            if let Some(binary_path) = find_installed(&version_dir(&options.platform_dir, tag))? {
+                return Ok(binary_path);
+            }
+            reporter.report(Status::CheckingForRelease);
+            source
+                .by_tag(repo, tag, options.github_token.as_ref())
+                .map_err(|err| format!("failed to find release '{tag}' of {repo}: {err}"))?
+        }
+    };
+
+    if let Some(version) = releases::parse_tag(&release.version) {
+        check_supported(&version, "upgrade or remove the pin")?;
+    }
+
+    let mut release = release;
+    if let Some(template) = &options.download_url_template {
+        for asset in &mut release.assets {
+            asset.download_url = mirror_url(template, &release.version, &asset.name);
+        }
+    }
+    let asset = find_asset(&release, options);
+
+    // Remembers the lookup of the latest release once it is installed.
+    let remember = || {
+        if options.version == ServerVersion::Latest {
+            release_cache::store(&CachedRelease::new(
+                release.version.clone(),
+                asset_name.clone(),
+                asset.map_or_else(String::new, |asset| asset.download_url.clone()),
+                repo.to_string(),
+                options.channel,
+                options.allow_prerelease,
+            ));
+        }
+    };
+
+    let version_dir = version_dir(&options.platform_dir, &release.version);
+    if let Some(binary_path) = find_installed(&version_dir)? {
+        remember();
+        return Ok(binary_path);
+    }
+
+    let replaced = reinstall && fs::metadata(&version_dir).is_ok();
+    if asset.is_none() && options.asset_name_template.is_some() && !options.build_from_source {
+        return Err(format!(
+            "release {} of {repo} {}, as named by `asset_name_template`",
+            release.version,
+            describe_assets(&release, options)
+        ));
+    }
+    let binary_path = match asset {
+        Some(asset) if !options.build_from_source => {
+            reporter.report(Status::Downloading {
+                tag: release.version.clone(),
+                asset: asset.name.clone(),
+                platform: platform_description(options),
+            });
+            install_staged(options, &release.version, |staging_dir| {
+                source.stage(options, &release, asset, staging_dir)
+            })
+            .map_err(|err| {
+                if options.libc != Some(Libc::Musl) || asset.name.contains(MUSL_SUFFIX) {
+                    return err;
+                }
+                format!(
+                    "{err}; this system uses musl libc, such as Alpine Linux, but release {} of \
+                     {repo} has no musl build, and its '{}' may need glibc; install glibc \
+                     compatibility such as Alpine's gcompat, or set `build_from_source`",
+                    release.version, asset.name
+                )
+            })?
+        }
+        _ => {
+            let go = find_go().ok_or_else(|| match asset {
+                Some(_) => "`build_from_source` is set, but go isn't on PATH".to_string(),
+                None => format!(
+                    "release {} of {repo} has no asset for {}, and go isn't on PATH to \
+                     build it from source; {}",
+                    release.version,
+                    platform_description(options),
+                    describe_assets(&release, options)
+                ),
+            })?;
+            reporter.report(Status::Building {
+                tag: release.version.clone(),
+            });
+            install_staged(options, &release.version, |staging_dir| {
+                build(&go, options, &release.version, staging_dir)
+            })?
+        }
+    };
+
+    if reinstall {
+        // A fresh download also clears a release that kept being corrupted.
+        fs::remove_file(format!(
+            "{}/{RECOVERED_PREFIX}{}",
+            options.platform_dir, release.version
+        ))
+        .ok();
+    }
+    if replaced {
+        log(format!(
+            "`force_reinstall` is set, so '{version_dir}' was removed and release {} of {repo} \
+             downloaded into it again; set it back to false before using it again",
+            release.version
+        ));
+    }
+    reporter.report(Status::Installed {
+        tag: release.version.clone(),
+        size: fs::metadata(&binary_path).map_or(0, |stat| stat.len()),
+    });
+    remember();
+    if options.channel == Channel::Nightly {
+        remove_old_nightlies(options, context_server_id, &version_dir);
+    } else {
+        remove_old_versions(
+            options,
+            context_server_id,
+            &version_dir,
+            is_prerelease(&release.version),
+        );
+    }
+    Ok(binary_path)
+}
+
+/// Where [`install`] looks up releases and downloads them from.
+///
+/// This is GitHub, or the mirror set by `download_url_template`, except in
+/// tests.
+trait Source {
+    /// Returns the latest release, as [`releases::latest`] does.
+    fn latest(
+        &self,
+        repo: &Repo,
+        allow_prerelease: bool,
+        token: Option<&Secret>,
+    ) -> Result<zed::GithubRelease>;
+
+    /// Returns the release tagged `tag`, as [`releases::by_tag`] does.
+    fn by_tag(&self, repo: &Repo, tag: &str, token: Option<&Secret>) -> Result<zed::GithubRelease>;
+
+    /// Returns the published releases, as [`releases::list`] does.
+    fn list(&self, repo: &Repo, token: Option<&Secret>) -> Result<Vec<zed::GithubRelease>>;
+
+    /// Downloads, verifies and extracts `asset` of `release` into
+    /// `staging_dir`, as [`stage`] does.
+    fn stage(
+        &self,
+        options: &BinaryOptions,
+        release: &zed::GithubRelease,
+        asset: &zed::GithubReleaseAsset,
+        staging_dir: &str,
+    ) -> Result<()>;
+}
+
+/// The releases published on GitHub.
+struct GitHub;
+
+impl Source for GitHub {
+    fn latest(
+        &self,
+        repo: &Repo,
+        allow_prerelease: bool,
+        token: Option<&Secret>,
+    ) -> Result<zed::GithubRelease> {
+        releases::latest(repo, allow_prerelease, token)
+    }
+
+    fn by_tag(&self, repo: &Repo, tag: &str, token: Option<&Secret>) -> Result<zed::GithubRelease> {
+        releases::by_tag(repo, tag, token)
+    }
+
+    fn list(&self, repo: &Repo, token: Option<&Secret>) -> Result<Vec<zed::GithubRelease>> {
+        releases::list(repo, token)
+    }
+
+    fn stage(
+        &self,
+        options: &BinaryOptions,
+        release: &zed::GithubRelease,
+        asset: &zed::GithubReleaseAsset,
+        staging_dir: &str,
+    ) -> Result<()> {
+        stage(options, release, asset, &asset.name, staging_dir)
+    }
+}
+
+/// Downloads, verifies and extracts `asset`, named `asset_name`, of
+/// `release` into `staging_dir`.
+fn stage(
+    options: &BinaryOptions,
+    release: &zed::GithubRelease,
+    asset: &zed::GithubReleaseAsset,
+    asset_name: &str,
+    staging_dir: &str,
+) -> Result<()> {
+    let repo = &options.repo;
+    if options.verify_checksum {
+        let checksums = integrity::checksums_asset(release).ok_or_else(|| {
+            format!(
+                "release {} of {repo} publishes no checksums.txt to verify \
+                 '{asset_name}' against; set `skip_checksum_verification` to install it \
+                 anyway",
+                release.version
+            )
+        })?;
+        let archive_path = format!("{staging_dir}/{asset_name}");
+        download(&asset.download_url, staging_dir, || {
+            zed::download_file(
+                &asset.download_url,
+                &archive_path,
+                zed::DownloadedFileType::Uncompressed,
+            )
+        })?;
+        let archive = fs::read(&archive_path)
+            .map_err(|err| format!("failed to read '{archive_path}': {err}"))?;
+        integrity::verify_checksum(
+            release,
+            checksums,
+            asset_name,
+            &archive,
+            options.signature.as_ref(),
+            staging_dir,
+        )?;
+        extract(&archive_path, staging_dir)?;
+        fs::remove_file(&archive_path).ok();
+    } else {
+        let file_kind = if asset_name.ends_with(".zip") {
+            zed::DownloadedFileType::Zip
+        } else {
+            zed::DownloadedFileType::GzipTar
+        };
+
+        download(&asset.download_url, staging_dir, || {
+            zed::download_file(&asset.download_url, staging_dir, file_kind)
+        })?;
+    }
+
+    prepare_binary(
+        staging_dir,
+        &release.version,
+        options.platform,
+        &options.binary_name,
+        matches!(options.version, ServerVersion::Tag(_)),
+        &format!("'{asset_name}' of release {}", release.version),
+    )
+}
+
+/// Builds release `tag` of the server from source into `staging_dir` with
+/// `go install`.
+fn build(go: &str, options: &BinaryOptions, tag: &str, staging_dir: &str) -> Result<()> {
+    let repo = &options.repo;
+    fs::create_dir_all(staging_dir)
+        .map_err(|err| format!("failed to create directory '{staging_dir}': {err}"))?;
+    let gobin = paths::in_work_dir(staging_dir)?;
+    let package = format!("{GO_PACKAGE}@{tag}");
+    // `go install` can only be told where to put the binary through GOBIN.
+    let argv = match options.platform {
+        zed::Os::Windows => vec![
+            "cmd".into(),
+            "/C".into(),
+            format!("set \"GOBIN={gobin}\" && \"{go}\" install {package}"),
+        ],
+        zed::Os::Mac | zed::Os::Linux => vec![
+            "env".into(),
+            format!("GOBIN={gobin}"),
+            go.into(),
+            "install".into(),
+            package.clone(),
+        ],
+    };
+    subprocess::run("go install", argv, GO_BUILD_TIMEOUT_SECS)
+        .map_err(|err| format!("failed to build release {tag} of {repo} from source: {err}"))?;
+    prepare_binary(
+        staging_dir,
+        tag,
+        options.platform,
+        BINARY_NAME,
+        matches!(options.version, ServerVersion::Tag(_)),
+        &format!("the build of {package}"),
+    )
+}
+
+/// Installs the release `tag` into its version directory in the platform
+/// directory of `options`, returning the path of its binary.
+///
+/// `stage` fills a staging directory, which is only renamed into place
+/// once complete, so that an interrupted install never looks installed.
+/// The release is then added to the manifest.
+fn install_staged(
+    options: &BinaryOptions,
+    tag: &str,
+    stage: impl FnOnce(&str) -> Result<()>,
+) -> Result<String> {
+    let platform_dir = &options.platform_dir;
+    fs::create_dir_all(platform_dir)
+        .map_err(|err| format!("failed to create directory '{platform_dir}': {err}"))?;
+    let version_dir = version_dir(platform_dir, tag);
+    remove_stale_staging_dirs(platform_dir);
+    let staging_dir = staging_dir_name(platform_dir, tag);
+    if let Err(err) = stage(&staging_dir) {
+        fs::remove_dir_all(&staging_dir).ok();
+        return Err(err);
+    }
+    fs::remove_dir_all(&version_dir).ok();
+    fs::rename(&staging_dir, &version_dir).map_err(|err| {
+        fs::remove_dir_all(&staging_dir).ok();
+        format!("failed to move '{staging_dir}' to '{version_dir}': {err}")
+    })?;
+    let binary_path = find_binary(&version_dir, options.platform)
+        .ok_or_else(|| format!("'{version_dir}' lost its binary while being installed"))?;
+    let mut manifest = load_manifest(options);
+    manifest.record(&version_dir, tag, &binary_path, None)?;
+    manifest.save();
+    Ok(binary_path)
+}
+
+/// Makes the binary of release `tag` extracted into `staging_dir` from
+/// `source` executable and checks that it runs, and marks the directory as
+/// pinned if `pinned` is set.
+///
+/// A binary named `binary_name` rather than `mcp-grafana` is renamed, so
+/// that it is found like any other install.
+fn prepare_binary(
+    staging_dir: &str,
+    tag: &str,
+    platform: zed::Os,
+    binary_name: &str,
+    pinned: bool,
+    source: &str,
+) -> Result<()> {
+    let binary_path = if binary_name == BINARY_NAME {
+        match find_binary(staging_dir, platform) {
+            Some(binary_path) => binary_path,
+            None => hoist_nested_binary(staging_dir, platform, binary_name, source)?,
+        }
+    } else {
+        let renamed = format!("{staging_dir}/{}", binary_file_name(platform, binary_name));
+        let renamed = if fs::metadata(&renamed).is_ok_and(|stat| stat.is_file()) {
+            renamed
+        } else {
+            hoist_nested_binary(staging_dir, platform, binary_name, source)?
+        };
+        let binary_path = format!("{staging_dir}/{}", binary_file_name(platform, BINARY_NAME));
+        fs::rename(&renamed, &binary_path)
+            .map_err(|err| format!("failed to rename the {binary_name} of {source}: {err}"))?;
+        binary_path
+    };
+    if platform != zed::Os::Windows {
+        zed::make_file_executable(&binary_path)?;
+    }
+    verify_binary(&binary_path, tag).map_err(|err| format!("{source} is broken: {err}"))?;
+    if pinned {
+        fs::write(format!("{staging_dir}/{PINNED_MARKER}"), "")
+            .map_err(|err| format!("failed to mark '{staging_dir}' as pinned: {err}"))?;
+    }
+    Ok(())
+}
+
+/// Installs the server from the local archive `archive_path`, unless the
+/// version it contains is already installed.
+///
+/// The version is `archive_version` of `options` if set, or otherwise taken
+/// from the
+/// file name, such as `mcp-grafana_0.2.5_Linux_x86_64.tar.gz`. The install
+/// is pinned, since it can't be downloaded again.
+fn install_archive(archive_path: &str, options: &BinaryOptions) -> Result<String> {
+    let file_name = archive_path
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(archive_path);
+    let version = match options.archive_version.as_deref() {
+        Some(version) => releases::parse_tag(version.trim()).ok_or_else(|| {
+            format!("invalid `archive_version` '{version}'; expected a version such as 0.2.5")
+        })?,
+        None => version_in_file_name(file_name).ok_or_else(|| {
+            format!(
+                "can't tell the version of the server in `archive_path` '{archive_path}' from \
+                 its name; set `archive_version`"
+            )
+        })?,
+    };
+    check_supported(&version, "use a newer archive")?;
+    let tag = format!("v{version}");
+    if let Some(binary_path) = installed_binary(&version_dir(&options.platform_dir, &tag), options)?
+    {
+        return Ok(binary_path);
+    }
+    install_staged(options, &tag, |staging_dir| {
+        fs::create_dir_all(staging_dir)
+            .map_err(|err| format!("failed to create directory '{staging_dir}': {err}"))?;
+        extract(archive_path, staging_dir)
+            .map_err(|err| format!("failed to extract `archive_path` '{archive_path}': {err}"))?;
+        prepare_binary(
+            staging_dir,
+            &tag,
+            options.platform,
+            &options.binary_name,
+            true,
+            &format!("`archive_path` '{archive_path}'"),
+        )
+    })
+}
+
+/// Returns the version in an archive's file name, such as 0.2.5 in
+/// `mcp-grafana_0.2.5_Linux_x86_64.tar.gz` or `mcp-grafana-v0.2.5.zip`.
+fn version_in_file_name(file_name: &str) -> Option<Version> {
+    file_name
+        .split(['_', '-'])
+        .flat_map(|part| {
+            // The last part still has the extension, such as `0.2.5.zip`.
+            [
+                part,
+                part.trim_end_matches(".tar.gz").trim_end_matches(".zip"),
+            ]
+        })
+        .find_map(releases::parse_tag)
+}
+
+/// Returns a staging directory in `platform_dir` for installing the
+/// release `tag`, unique to this attempt.
+fn staging_dir_name(platform_dir: &str, tag: &str) -> String {
+    // `std::process::id` isn't available to extensions, so use the time.
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_nanos());
+    format!("{platform_dir}/{STAGING_PREFIX}{tag}-{nanos}")
+}
+
+/// Removes the staging directories in `platform_dir` of installs which
+/// were interrupted, leaving any which may still be in progress.
+fn remove_stale_staging_dirs(platform_dir: &str) {
+    let Ok(entries) = fs::read_dir(platform_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let stale = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(STAGING_PREFIX)
+            && entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_none_or(|age| age > STALE_STAGING_AGE);
+        if stale {
+            fs::remove_dir_all(entry.path()).ok();
+        }
+    }
+}
+
+/// Returns the newest installed release satisfying `req`, if any, for when
+/// looking up releases failed with `err`.
+fn use_installed(options: &BinaryOptions, req: Option<&VersionReq>, err: String) -> Result<String> {
+    let Some((version, binary_path)) = newest_installed(options, req) else {
+        return Err(format!(
+            "{err}; no release of the server is installed to fall back to"
+        ));
+    };
+    log(format!(
+        "running offline on the installed release v{version} of the server, since looking up \
+         releases failed: {err}"
+    ));
+    Ok(binary_path)
+}
+
+/// Returns the newest installed release satisfying `req`, or any stable
+/// release without one, along with the path of its binary.
+fn newest_installed(
+    options: &BinaryOptions,
+    req: Option<&VersionReq>,
+) -> Option<(Version, String)> {
+    let mut installed = installed_versions(&load_manifest(options), &options.platform_dir);
+    installed.sort_by(|(a, _), (b, _)| b.cmp(a));
+    installed.into_iter().find_map(|(version, dir)| {
+        let usable = version >= MIN_SUPPORTED_VERSION
+            && match req {
+                Some(req) => releases::matches(req, &version, options.allow_prerelease),
+                None => options.allow_prerelease || version.pre.is_empty(),
+            };
+        if !usable {
+            return None;
+        }
+        // A corrupted release is reported once it is selected again.
+        Some((version, installed_binary(&dir, options).ok()??))
+    })
+}
+
+/// Removes the version directories in the platform directory of `options`
+/// which [`outdated_versions`] returns, except pinned ones, ones another
+/// context server than `context_server_id` runs, and stable ones if
+/// `keep_stable` is set.
+///
+/// Only directories named like `mcp-grafana-v0.2.5` are removed, so that
+/// anything else, such as staging directories, is left alone. Other
+/// platforms' releases are never removed, since they may be in use on
+/// another machine sharing the directory.
+fn remove_old_versions(
+    options: &BinaryOptions,
+    context_server_id: &str,
+    version_dir: &str,
+    keep_stable: bool,
+) {
+    let mut manifest = load_manifest(options);
+    let installed = installed_versions(&manifest, &options.platform_dir);
+    for (version, dir) in outdated_versions(installed, version_dir, options.keep_versions) {
+        let keep = fs::metadata(format!("{dir}/{PINNED_MARKER}")).is_ok()
+            || manifest.is_active_elsewhere(&dir, context_server_id)
+            || (keep_stable && version.pre.is_empty());
+        if !keep {
+            fs::remove_dir_all(&dir).ok();
+            manifest.remove(&dir);
+        }
+    }
+    manifest.save();
+}
+
+/// Returns the version directories among `installed` beyond the newest
+/// `keep` by version, other than `version_dir`, which is always kept.
+///
+/// Directories of the same version are ordered by name, so that the
+/// result doesn't depend on the order of `installed`.
+fn outdated_versions(
+    mut installed: Vec<(Version, String)>,
+    version_dir: &str,
+    keep: usize,
+) -> Vec<(Version, String)> {
+    installed.sort_by(|(a, a_dir), (b, b_dir)| b.cmp(a).then_with(|| a_dir.cmp(b_dir)));
+    installed
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (_, dir))| *index >= keep && dir != version_dir)
+        .map(|(_, installed)| installed)
+        .collect()
+}
+
+/// Returns the releases of the server which `manifest` lists in
+/// `platform_dir`, along with their version directories, in no particular
+/// order.
+fn installed_versions(manifest: &Manifest, platform_dir: &str) -> Vec<(Version, String)> {
+    manifest
+        .installs_in(platform_dir)
+        .filter_map(|(dir, _)| {
+            let (_, name) = dir.rsplit_once('/')?;
+            Some((version_of_dir(name)?, dir.to_string()))
+        })
+        .collect()
+}
+
+/// Returns the most recently installed nightly build matching the tag
+/// pattern of `options`, along with the path of its binary.
+fn newest_nightly(options: &BinaryOptions) -> Option<(String, String)> {
+    installed_nightlies(&load_manifest(options), &options.platform_dir)
+        .into_iter()
+        .filter(|(tag, _)| tool_names::glob_matches(&options.nightly_tag_pattern, tag))
+        .find_map(|(tag, dir)| Some((tag, installed_binary(&dir, options).ok()??)))
+}
+
+/// Removes the nightly builds in the platform directory of `options`
+/// beyond the `keep_versions` most recently installed, other than
+/// `version_dir` and ones another context server than `context_server_id`
+/// runs.
+fn remove_old_nightlies(options: &BinaryOptions, context_server_id: &str, version_dir: &str) {
+    let mut manifest = load_manifest(options);
+    for (_, dir) in installed_nightlies(&manifest, &options.platform_dir)
+        .into_iter()
+        .skip(options.keep_versions)
+    {
+        if dir != version_dir && !manifest.is_active_elsewhere(&dir, context_server_id) {
+            fs::remove_dir_all(&dir).ok();
+            manifest.remove(&dir);
+        }
+    }
+    manifest.save();
+}
+
+/// Returns the tags and version directories of the nightly builds which
+/// `manifest` lists in `platform_dir`, most recently installed first.
+///
+/// Nightly tags aren't necessarily versions, so builds are ordered by when
+/// they were installed.
+fn installed_nightlies(manifest: &Manifest, platform_dir: &str) -> Vec<(String, String)> {
+    let mut installed: Vec<(Duration, String, String)> = manifest
+        .installs_in(platform_dir)
+        .map(|(dir, install)| {
+            (
+                manifest.age(dir).unwrap_or(Duration::MAX),
+                install.tag.clone(),
+                dir.to_string(),
+            )
+        })
+        .collect();
+    installed.sort();
+    installed
+        .into_iter()
+        .map(|(_, tag, dir)| (tag, dir))
+        .collect()
+}
+
+/// Returns the manifest of the installed releases, which is regenerated
+/// from the version directories of the platform of `options` if it is
+/// missing or corrupted.
+///
+/// The servers aren't run to regenerate it, since whole version
+/// directories are only ever moved into place once installed. Other
+/// platforms' releases are added by their machines.
+fn load_manifest(options: &BinaryOptions) -> Manifest {
+    let platform = options.platform;
+    let own_dir = platform_dir(platform, options.arch);
+    Manifest::load(|| {
+        let mut manifest = Manifest::default();
+        for platform_dir in platform_dirs(options.storage_dir.as_deref()) {
+            let name = platform_dir.rsplit('/').next().unwrap_or(&platform_dir);
+            if name != own_dir {
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(&platform_dir) else {
+                continue;
+            };
+            for name in entries
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+                .filter_map(|entry| entry.file_name().into_string().ok())
+            {
+                let Some(tag) = tag_of_dir(&name) else {
+                    continue;
+                };
+                let version_dir = format!("{platform_dir}/{name}");
+                let Some(binary_path) = find_binary(&version_dir, platform) else {
+                    continue;
+                };
+                let installed_at = modified_at(&version_dir);
+                if let Err(err) = manifest.record(&version_dir, tag, &binary_path, installed_at) {
+                    log(format!(
+                        "failed to add '{version_dir}' to the manifest: {err}"
+                    ));
+                }
+            }
+        }
+        manifest
+    })
+}
+
+/// Records in the manifest that `context_server_id` runs `binary_path`, or
+/// a binary the extension didn't install if it is `None`.
+fn set_active(context_server_id: &str, options: &BinaryOptions, binary_path: Option<&str>) {
+    let mut manifest = load_manifest(options);
+    manifest.set_active(context_server_id, binary_path);
+    manifest.save();
+}
+
+/// Returns when `path` was last modified, which stands in for when a
+/// release missing from the manifest was installed.
+fn modified_at(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Returns the platform directories in the extension's working
+/// directory and in `storage_dir`, including those of nightly builds and
+/// of other repositories.
+fn platform_dirs(storage_dir: Option<&str>) -> Vec<String> {
+    let mut parents = vec![];
+    for root in iter::once(None).chain(storage_dir.map(Some)) {
+        parents.extend([in_storage(root, "."), in_storage(root, NIGHTLY_DIR)]);
+        if let Ok(entries) = fs::read_dir(in_storage(root, REPOS_DIR)) {
+            parents.extend(
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().into_string().ok())
+                    .map(|name| in_storage(root, &format!("{REPOS_DIR}/{name}"))),
+            );
+        }
+    }
+    parents
+        .into_iter()
+        .filter_map(|dir| fs::read_dir(&dir).ok().map(|entries| (dir, entries)))
+        .flat_map(|(dir, entries)| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| is_platform_dir(name))
+                .map(move |name| match dir.as_str() {
+                    "." => name,
+                    _ => format!("{dir}/{name}"),
+                })
+        })
+        .collect()
+}
+
+/// Returns the directory of the extension's working directory which
+/// releases for `platform` and `arch` are installed in, such as
+/// `darwin-arm64`.
+///
+/// Keeping platforms apart lets machines of different platforms share the
+/// working directory, such as on an NFS-mounted home directory.
+fn platform_dir(platform: zed::Os, arch: zed::Architecture) -> String {
+    format!("{}-{}", os_name(platform).to_lowercase(), arch_name(arch))
+}
+
+/// Returns whether `name` is a directory which [`platform_dir`] returns.
+fn is_platform_dir(name: &str) -> bool {
+    [zed::Os::Mac, zed::Os::Linux, zed::Os::Windows]
+        .into_iter()
+        .flat_map(|platform| {
+            [
+                zed::Architecture::Aarch64,
+                zed::Architecture::X86,
+                zed::Architecture::X8664,
+            ]
+            .map(|arch| platform_dir(platform, arch))
+        })
+        .any(|dir| dir == name)
+}
+
+/// Returns `path` within the directory releases are installed in, which is
+/// `storage_dir` if set, or else the extension's working directory.
+///
+/// Every platform directory is built from this, so that installs and
+/// cleanup alike respect `storage_dir`.
+fn in_storage(storage_dir: Option<&str>, path: &str) -> String {
+    match (storage_dir, path) {
+        (None, path) => path.into(),
+        (Some(dir), ".") => dir.into(),
+        (Some(dir), path) => format!("{dir}/{path}"),
+    }
+}
+
+/// Returns the `storage_dir` of `settings`, with `~` expanded, if set.
+fn storage_dir(settings: &GrafanaContextServerSettings) -> Result<Option<String>> {
+    let Some(dir) = settings.storage_dir.as_deref() else {
+        return Ok(None);
+    };
+    let dir = paths::expand_home("storage_dir", dir.trim())?;
+    let dir = dir.trim_end_matches(['/', '\\']);
+    if !Path::new(dir).is_absolute() {
+        return Err(format!(
+            "`storage_dir` must be an absolute path, since relative paths cannot be resolved \
+             against the worktree: '{dir}'"
+        ));
+    }
+    Ok(Some(dir.into()))
+}
+
+/// Returns the directory release `tag` is installed in, within
+/// `platform_dir`.
+///
+/// Every path of an installed release is built from this, which keeps
+/// the cleanup in [`remove_old_versions`] and [`purge`] consistent with it.
+fn version_dir(platform_dir: &str, tag: &str) -> String {
+    format!("{platform_dir}/{BINARY_NAME}-{tag}")
+}
+
+/// Moves the releases installed before they were kept apart by platform
+/// into the platform directory of `options`, if they run on its platform.
+///
+/// Releases which don't run are left for a machine of their platform to
+/// move, and are removed by [`purge`]. Since checking that they run means
+/// running them, this is only done once per platform, which is recorded
+/// in a [`MIGRATED_PREFIX`] file.
+fn migrate_legacy_dirs(options: &BinaryOptions) {
+    let platform = options.platform;
+    let marker = format!("{MIGRATED_PREFIX}{}", platform_dir(platform, options.arch));
+    if fs::metadata(&marker).is_ok() {
+        return;
+    }
+    let platform_dir = &in_storage(
+        options.storage_dir.as_deref(),
+        &platform_dir(platform, options.arch),
+    );
+    let Ok(entries) = fs::read_dir(".") else {
+        return;
+    };
+    for name in entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+    {
+        let Some(tag) = tag_of_dir(&name) else {
+            continue;
+        };
+        let target = version_dir(platform_dir, tag);
+        let runs = find_binary(&name, platform)
+            .is_some_and(|binary_path| verify_binary(&binary_path, tag).is_ok());
+        if !runs || fs::metadata(&target).is_ok() {
+            continue;
+        }
+        let moved = fs::create_dir_all(platform_dir).and_then(|()| fs::rename(&name, &target));
+        match moved {
+            Ok(()) => log(format!("moved '{name}' to '{target}'")),
+            Err(err) => {
+                log(format!("failed to move '{name}' to '{target}': {err}"));
+                continue;
+            }
+        }
+        if let Some(binary_path) = find_binary(&target, platform) {
+            let mut manifest = load_manifest(options);
+            match manifest.record(&target, tag, &binary_path, modified_at(&target)) {
+                Ok(()) => manifest.save(),
+                Err(err) => log(format!("failed to add '{target}' to the manifest: {err}")),
+            }
+        }
+    }
+    if let Err(err) = fs::write(&marker, "") {
+        log(format!("failed to write '{marker}': {err}"));
+    }
+}
+
+/// Returns the tag of the release installed in the directory `name`, if it
+/// is named like a version directory.
+fn tag_of_dir(name: &str) -> Option<&str> {
+    name.strip_prefix(BINARY_NAME)?.strip_prefix('-')
+}
+
+/// Removes every installed release, in the extension's working directory
+/// and the `storage_dir` of `options`, along with the extension's other
+/// records of them, so that the server is downloaded again from scratch.
+fn purge(options: &BinaryOptions) {
+    let mut removed = vec![];
+    for root in iter::once(None).chain(options.storage_dir.as_deref().map(Some)) {
+        let Ok(entries) = fs::read_dir(in_storage(root, ".")) else {
+            continue;
+        };
+        for name in entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+        {
+            let path = in_storage(root, &name);
+            let removed_entry = if tag_of_dir(&name).is_some()
+                || name.starts_with(STAGING_PREFIX)
+                || is_platform_dir(&name)
+                || name == NIGHTLY_DIR
+                || name == REPOS_DIR
+            {
+                fs::remove_dir_all(&path).is_ok()
+            } else if name.starts_with(RECOVERED_PREFIX) || name.starts_with(MIGRATED_PREFIX) {
+                fs::remove_file(&path).is_ok()
+            } else {
+                false
+            };
+            if removed_entry {
+                removed.push(path);
+            }
+        }
+    }
+    if release_cache::clear() {
+        removed.push("the last release lookup".into());
+    }
+    if manifest::clear() {
+        removed.push("the manifest".into());
+    }
+    if removed.is_empty() {
+        log("purged the installed servers, but none were installed");
+    } else {
+        log(format!(
+            "purged the installed servers, removing {}",
+            removed.join(", ")
+        ));
+    }
+}
+
+/// Returns the version of the release installed in the directory `name`,
+/// or `None` unless it is named like `mcp-grafana-v0.2.5`.
+fn version_of_dir(name: &str) -> Option<Version> {
+    let tag = tag_of_dir(name)?;
+    if !tag.starts_with('v') {
+        return None;
+    }
+    releases::parse_tag(tag)
+}
+
+/// Returns the download URL of the asset `asset_name` of the release `tag`
+/// given by `template`, by replacing its `{tag}`, `{version}` and `{asset}`
+/// placeholders.
+fn mirror_url(template: &str, tag: &str, asset_name: &str) -> String {
+    template
+        .replace("{tag}", tag)
+        .replace("{version}", tag.strip_prefix('v').unwrap_or(tag))
+        .replace("{asset}", asset_name)
+}
+
+/// Returns the template of download URLs given by `download_base_url` or
+/// `download_url_template`, if either is set.
+fn download_url_template(settings: &GrafanaContextServerSettings) -> Result<Option<String>> {
+    let template = match (&settings.download_base_url, &settings.download_url_template) {
+        (None, None) => return Ok(None),
+        (Some(_), Some(_)) => {
+            return Err(
+                "both `download_base_url` and `download_url_template` are set; configure only \
+                 one of them"
+                    .into(),
+            );
+        }
+        (Some(base_url), None) => format!("{}/{{tag}}/{{asset}}", base_url.trim_end_matches('/')),
+        (None, Some(template)) => {
+            if !template.contains("{asset}") {
+                return Err(format!(
+                    "`download_url_template` '{template}' must contain `{{asset}}`, which is \
+                     replaced with the name of the asset to download"
+                ));
+            }
+            template.clone()
+        }
+    };
+    if !template.starts_with("https://") && !template.starts_with("http://") {
+        return Err(format!(
+            "the download URL '{template}' must start with https:// or http://"
+        ));
+    }
+    Ok(Some(template))
+}
+
+/// Returns why `release` can't be installed yet, if it can't: it has no
+/// asset for the platform of `options`, or no checksums to verify it
+/// against.
+///
+/// Upstream publishes a release before its assets finish uploading, so
+/// this is usually temporary.
+fn unready_reason(release: &zed::GithubRelease, options: &BinaryOptions) -> Option<String> {
+    if find_asset(release, options).is_none() {
+        return Some(describe_assets(release, options));
+    }
+    if options.verify_checksum && integrity::checksums_asset(release).is_none() {
+        return Some("publishes no checksums.txt to verify its assets against".into());
+    }
+    None
+}
+
+/// Returns the newest of the releases before `latest` which
+/// [`unready_reason`] finds ready, looking at most
+/// [`MAX_FALLBACK_RELEASES`] releases back.
+///
+/// `latest` is skipped for `reason`, and looked up again once the lookup
+/// of the latest release is `release_check_interval_seconds` old. The
+/// earlier releases are listed by `source`.
+fn fall_back_from(
+    latest: zed::GithubRelease,
+    reason: &str,
+    options: &BinaryOptions,
+    source: &dyn Source,
+) -> Result<zed::GithubRelease> {
+    let repo = &options.repo;
+    log(format!(
+        "skipping release {} of {repo} for now, which {reason}; its assets may still be \
+         uploading, so it is tried again in {}s",
+        latest.version, options.release_check_interval_seconds
+    ));
+    let mut releases = source.list(repo, options.github_token.as_ref())?;
+    releases::sort_newest_first(&mut releases);
+    let earlier = releases
+        .into_iter()
+        .skip_while(|release| release.version != latest.version)
+        .skip(1)
+        .filter(|release| options.allow_prerelease || !is_prerelease(&release.version))
+        .take(MAX_FALLBACK_RELEASES);
+    for release in earlier {
+        let Some(reason) = unready_reason(&release, options) else {
+            log(format!("installing release {} instead", release.version));
+            return Ok(release);
+        };
+        log(format!(
+            "skipping release {} of {repo}, which {reason}",
+            release.version
+        ));
+    }
+    Err(format!(
+        "none of the latest {} releases of {repo} can be installed for {}; release {} {reason}",
+        MAX_FALLBACK_RELEASES + 1,
+        platform_description(options),
+        latest.version
+    ))
+}
+
+/// Runs `download_file`, which downloads `url` into `dir`, with
+/// retries.
+///
+/// `dir` is emptied before every attempt, so that nothing is left
+/// of a failed one.
+fn download(url: &str, dir: &str, download_file: impl Fn() -> Result<()>) -> Result<()> {
+    retry::with_backoff(&format!("downloading {url}"), || {
+        fs::remove_dir_all(dir).ok();
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("failed to create directory '{dir}': {err}"))?;
+        download_file()
+    })
+    .map_err(|e| format!("failed to download {url}: {e}"))
+}
+
+/// Returns whether the release `tag` is a pre-release, such as
+/// `v1.0.0-rc.1`.
+fn is_prerelease(tag: &str) -> bool {
+    releases::parse_tag(tag).is_some_and(|version| !version.pre.is_empty())
+}
+
+/// The values of the placeholders of an asset name template.
+struct AssetName<'a> {
+    tag: &'a str,
+    os: &'a str,
+    arch: &'a str,
+    goos: &'a str,
+    goarch: &'a str,
+    ext: &'a str,
+}
+
+impl<'a> AssetName<'a> {
+    /// Returns the values for release `tag` on `platform` and `arch`,
+    /// with the names the server's current assets use.
+    fn new(tag: &'a str, platform: zed::Os, arch: zed::Architecture) -> Self {
+        Self {
+            tag,
+            os: os_name(platform),
+            arch: arch_name(arch),
+            goos: match platform {
+                zed::Os::Mac => "darwin",
+                zed::Os::Linux => "linux",
+                zed::Os::Windows => "windows",
+            },
+            goarch: match arch {
+                zed::Architecture::Aarch64 => "arm64",
+                zed::Architecture::X86 => "386",
+                zed::Architecture::X8664 => "amd64",
+            },
+            ext: match platform {
+                zed::Os::Mac | zed::Os::Linux => "tar.gz",
+                zed::Os::Windows => "zip",
+            },
+        }
+    }
+
+    /// Renders `template`, replacing its `{tag}`, `{version}` (the tag
+    /// without its `v`), `{os}`, `{arch}`, `{goos}`, `{goarch}` and `{ext}`
+    /// placeholders.
+    fn render(&self, template: &str) -> String {
+        template
+            .replace("{tag}", self.tag)
+            .replace("{version}", self.tag.strip_prefix('v').unwrap_or(self.tag))
+            .replace("{os}", self.os)
+            .replace("{arch}", self.arch)
+            .replace("{goos}", self.goos)
+            .replace("{goarch}", self.goarch)
+            .replace("{ext}", self.ext)
+    }
+}
+
+/// Checks that `asset_name_template` has no placeholders other than those
+/// [`AssetName::render`] replaces.
+fn check_asset_name_template(template: &str) -> Result<()> {
+    let rendered =
+        AssetName::new("v0.0.0", zed::Os::Linux, zed::Architecture::X8664).render(template.trim());
+    if template.trim().is_empty() || rendered.contains(['{', '}']) {
+        return Err(format!(
+            "invalid `asset_name_template` '{template}'; expected a name with the placeholders \
+             {{version}}, {{tag}}, {{os}}, {{arch}}, {{goos}}, {{goarch}} or {{ext}}, such as \
+             'mcp-grafana-{{version}}-{{goos}}-{{goarch}}.tgz'"
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the names which the asset of release `tag` for the platform of
+/// `options` may have, in order of preference.
+///
+/// This is the name `asset_name_template` gives if it is set. Otherwise
+/// the current scheme, such as `mcp-grafana_Darwin_arm64.tar.gz`, comes
+/// first, followed by the Go OS and architecture names, such as
+/// `mcp-grafana_darwin_amd64.tar.gz`, which upstream has used too, and the
+/// other archive format. Builds for musl come first where `libc` is musl.
+fn asset_names(options: &BinaryOptions, tag: &str) -> Vec<String> {
+    let values = AssetName::new(tag, options.platform, options.arch);
+    if let Some(template) = &options.asset_name_template {
+        return vec![values.render(template)];
+    }
+    let templates: &[&str] = match options.libc {
+        Some(Libc::Musl) => &[MUSL_ASSET_NAME_TEMPLATE, ASSET_NAME_TEMPLATE],
+        Some(Libc::Gnu) | None => &[ASSET_NAME_TEMPLATE],
+    };
+    let exts = match options.platform {
+        zed::Os::Mac | zed::Os::Linux => ["tar.gz", "zip"],
+        zed::Os::Windows => ["zip", "tar.gz"],
+    };
+
+    let mut names: Vec<String> = vec![];
+    for template in templates {
+        for ext in exts {
+            for os in [values.os, values.goos] {
+                for arch in [values.arch, values.goarch] {
+                    let name = AssetName {
+                        os,
+                        arch,
+                        ext,
+                        ..values
+                    }
+                    .render(template);
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Returns what identifies the asset for the platform of `options`
+/// in the persisted release lookup, whichever release it belongs to.
+fn asset_key(options: &BinaryOptions) -> String {
+    match &options.asset_name_template {
+        Some(template) => template.clone(),
+        None => asset_names(options, "").swap_remove(0),
+    }
+}
+
+/// Returns the asset of `release` with the first of the names
+/// [`asset_names`] returns for it.
+fn find_asset<'a>(
+    release: &'a zed::GithubRelease,
+    options: &BinaryOptions,
+) -> Option<&'a zed::GithubReleaseAsset> {
+    asset_names(options, &release.version)
+        .iter()
+        .find_map(|name| release.assets.iter().find(|asset| &asset.name == name))
+}
+
+/// Says that `release` has none of the names [`asset_names`] returns for
+/// it, listing the assets it has instead, to show when upstream renamed
+/// its assets.
+fn describe_assets(release: &zed::GithubRelease, options: &BinaryOptions) -> String {
+    let present: Vec<&str> = release
+        .assets
+        .iter()
+        .map(|asset| asset.name.as_str())
+        .collect();
+    format!(
+        "has none of the assets {}; it has {}",
+        asset_names(options, &release.version).join(", "),
+        if present.is_empty() {
+            "no assets".to_string()
+        } else {
+            present.join(", ")
+        }
+    )
+}
+
+/// Returns the tag of the latest release for `options`, which is looked up
+/// at most every `release_check_interval_seconds`.
+pub(crate) fn latest_tag(options: &BinaryOptions) -> Result<String> {
+    let asset_name = asset_key(options);
+    let max_age = Duration::from_secs(options.release_check_interval_seconds);
+    if let Some(cached) = release_cache::load(
+        &asset_name,
+        &options.repo.to_string(),
+        options.channel,
+        options.allow_prerelease,
+        max_age,
+    ) {
+        return Ok(cached.tag);
+    }
+    let latest = releases::latest(
+        &options.repo,
+        options.allow_prerelease,
+        options.github_token.as_ref(),
+    )?;
+    if let Some(asset) = find_asset(&latest, options) {
+        release_cache::store(&CachedRelease::new(
+            latest.version.clone(),
+            asset_name,
+            asset.download_url.clone(),
+            options.repo.to_string(),
+            options.channel,
+            options.allow_prerelease,
+        ));
+    }
+    Ok(latest.version)
+}
+
+/// Logs that a release newer than `binary_path` exists, if one does,
+/// when a pin or `auto_update` keeps the server from being upgraded.
+///
+/// Failing to look up the latest release is ignored, since this is only
+/// informational.
+fn log_newer_release(options: &BinaryOptions, binary_path: &str) {
+    let Some(installed) = binary_path.rsplit('/').nth(1).and_then(version_of_dir) else {
+        return;
+    };
+    let Ok(latest) = latest_tag(options) else {
+        return;
+    };
+    if releases::parse_tag(&latest).is_some_and(|latest| latest > installed) {
+        let reason = match &options.version {
+            ServerVersion::Latest => "`auto_update` is off".to_string(),
+            ServerVersion::Tag(tag) => format!("pinned to {tag}"),
+            ServerVersion::Range(req) => format!("pinned to {req}, using v{installed}"),
+        };
+        log(format!("{BINARY_NAME} {latest} is available ({reason})"));
+    }
+}
+
+/// Returns the name of `platform` in the server's asset names.
+fn os_name(platform: zed::Os) -> &'static str {
+    match platform {
+        zed::Os::Mac => "Darwin",
+        zed::Os::Linux => "Linux",
+        zed::Os::Windows => "Windows",
+    }
+}
+
+/// Returns the name of `arch` in the server's asset names, which is also
+/// its value in the `arch` setting.
+fn arch_name(arch: zed::Architecture) -> &'static str {
+    match arch {
+        zed::Architecture::Aarch64 => "arm64",
+        zed::Architecture::X86 => "i386",
+        zed::Architecture::X8664 => "x86_64",
+    }
+}
+
+/// Describes the platform the server is installed for, and whether its
+/// architecture was detected, so that misdetection shows in errors.
+fn platform_description(options: &BinaryOptions) -> String {
+    let source = match options.arch_reason {
+        ArchReason::Detected => "as detected; set `arch` if this is wrong",
+        ArchReason::Setting => "set by `arch`",
+        ArchReason::Rosetta => {
+            "since Zed runs under Rosetta on Apple Silicon; set `prefer_native_arch` to false \
+             for x86_64"
+        }
+    };
+    format!(
+        "{} {} ({source})",
+        os_name(options.platform),
+        arch_name(options.arch)
+    )
+}
+
+/// Returns the file name of the server binary `binary_name` on `platform`.
+fn binary_file_name(platform: zed::Os, binary_name: &str) -> String {
+    match platform {
+        zed::Os::Windows => format!("{binary_name}.exe"),
+        zed::Os::Mac | zed::Os::Linux => binary_name.into(),
+    }
+}
+
+/// Returns the path of the binary installed in `version_dir`, if there is
+/// one which works.
+///
+/// A binary which no longer matches the manifest is removed
+/// so that it is installed again, unless that already happened once
+/// without the binary being found intact since, which is an error. Installs
+/// from before binaries were verified are checked now, and treated the
+/// same way if they're broken.
+fn installed_binary(version_dir: &str, options: &BinaryOptions) -> Result<Option<String>> {
+    let Some(binary_path) = find_binary(version_dir, options.platform) else {
+        return Ok(None);
+    };
+    let (parent, name) = version_dir.rsplit_once('/').unwrap_or((".", version_dir));
+    let tag = tag_of_dir(name).unwrap_or(name);
+    let recovered_marker = format!("{parent}/{RECOVERED_PREFIX}{tag}");
+    let mut manifest = load_manifest(options);
+    let problem = match manifest.check(version_dir, &binary_path, true) {
+        Check::Intact => {
+            fs::remove_file(&recovered_marker).ok();
+            return Ok(Some(binary_path));
+        }
+        Check::Corrupted(problem) => problem,
+        Check::Missing => {
+            let verified = verify_binary(&binary_path, tag).and_then(|()| {
+                manifest.record(version_dir, tag, &binary_path, modified_at(version_dir))
+            });
+            match verified {
+                Ok(()) => {
+                    manifest.save();
+                    return Ok(Some(binary_path));
+                }
+                Err(err) => format!("its server is broken: {err}"),
+            }
+        }
+    };
+    if fs::metadata(&recovered_marker).is_ok() {
+        return Err(format!(
+            "'{version_dir}' was corrupted again after being reinstalled, since {problem}; \
+             something such as antivirus software may be modifying it. Once that is fixed, \
+             delete '{recovered_marker}' in the extension's directory to reinstall it"
+        ));
+    }
+    log(format!("reinstalling '{version_dir}', since {problem}"));
+    fs::remove_dir_all(version_dir).ok();
+    manifest.remove(version_dir);
+    manifest.save();
+    fs::write(&recovered_marker, "").ok();
+    Ok(None)
+}
+
+/// Checks that the server at `binary_path` runs, and that it reports being
+/// release `tag`.
+///
+/// This catches truncated downloads and binaries for another architecture,
+/// which would otherwise only fail once Zed starts the server.
+fn verify_binary(binary_path: &str, tag: &str) -> Result<()> {
+    let reported = reported_version(binary_path)?;
+    match (reported, releases::parse_tag(tag)) {
+        (Some(reported), Some(expected)) if reported != expected => Err(format!(
+            "it reports being version {reported}, not {expected}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the version printed by `binary_path --version`, if it prints
+/// one.
+///
+/// Releases without a `--version` flag are only checked to run with `-h`.
+fn reported_version(binary_path: &str) -> Result<Option<Version>> {
+    let binary = paths::in_work_dir(binary_path)?;
+    let run = |flag: &str| {
+        subprocess::run(
+            "Grafana MCP server",
+            vec![binary.clone(), flag.into()],
+            VERIFY_TIMEOUT_SECS,
+        )
+    };
+    let output = match run("--version") {
+        Ok(output) => output,
+        // Go's flag package rejects unknown flags like this.
+        Err(err) if err.contains("flag provided but not defined") => {
+            run("-h")?;
+            return Ok(None);
+        }
+        Err(err) => return Err(err),
+    };
+    let printed = [output.stdout, output.stderr].concat();
+    Ok(String::from_utf8_lossy(&printed)
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .find_map(releases::parse_tag))
+}
+
+/// Returns the path of the server binary extracted into `version_dir`, if
+/// there is one.
+///
+/// Falls back to any file in `version_dir` which looks like the binary,
+/// such as `mcp-grafana_windows_amd64.exe`, if the expected name isn't
+/// there.
+fn find_binary(version_dir: &str, platform: zed::Os) -> Option<String> {
+    let is_file = |path: &str| fs::metadata(path).is_ok_and(|stat| stat.is_file());
+    let exact = format!("{version_dir}/{}", binary_file_name(platform, BINARY_NAME));
+    if is_file(&exact) {
+        return Some(exact);
+    }
+    let looks_like_binary = |name: &str| {
+        name.starts_with(BINARY_NAME)
+            && match platform {
+                zed::Os::Windows => name.ends_with(".exe"),
+                zed::Os::Mac | zed::Os::Linux => !name.contains('.'),
+            }
+    };
+    fs::read_dir(version_dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| looks_like_binary(name))
+        .map(|name| format!("{version_dir}/{name}"))
+        .find(|path| is_file(path))
+}
+
+/// Moves the binary which `source` extracted into a subdirectory of
+/// `staging_dir`, such as `mcp-grafana_0.2.5_Linux_x86_64/mcp-grafana`, to
+/// the top of it, and returns its new path.
+///
+/// Directories are searched [`MAX_ARCHIVE_DEPTH`] levels deep. Finding no
+/// binary or several is an error listing what the archive contained.
+fn hoist_nested_binary(
+    staging_dir: &str,
+    platform: zed::Os,
+    binary_name: &str,
+    source: &str,
+) -> Result<String> {
+    let file_name = binary_file_name(platform, binary_name);
+    let mut files = vec![];
+    let mut dirs = vec![(String::new(), 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(format!("{staging_dir}/{dir}")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{dir}/{name}")
+            };
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                if depth < MAX_ARCHIVE_DEPTH {
+                    dirs.push((path, depth + 1));
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    let candidates: Vec<&String> = files
+        .iter()
+        .filter(|path| path.rsplit('/').next() == Some(file_name.as_str()))
+        .collect();
+    match candidates.as_slice() {
+        [nested] => {
+            let binary_path = format!("{staging_dir}/{file_name}");
+            fs::rename(format!("{staging_dir}/{nested}"), &binary_path).map_err(|err| {
+                format!("failed to move '{nested}' of {source} into place: {err}")
+            })?;
+            Ok(binary_path)
+        }
+        [] if files.is_empty() => Err(format!("{source} is empty")),
+        [] => Err(format!(
+            "{source} contains no {file_name}; it contains {}",
+            files.join(", ")
+        )),
+        several => Err(format!(
+            "{source} contains several {file_name} binaries, so it's unclear which to use: {}",
+            several
+                .iter()
+                .map(|path| path.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Extracts the downloaded `archive` into `dir`, both relative to the
+/// extension's working directory.
+///
+/// The extension API can only extract archives while downloading them, so
+/// this runs `tar`, which also reads zip archives on Windows.
+fn extract(archive: &str, dir: &str) -> Result<()> {
+    subprocess::run(
+        "tar",
+        vec![
+            "tar".into(),
+            "-xf".into(),
+            paths::in_work_dir(archive)?,
+            "-C".into(),
+            paths::in_work_dir(dir)?,
+        ],
+        EXTRACT_TIMEOUT_SECS,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, env, sync::Mutex};
+
+    use zed_extension_api::serde_json::json;
+
+    use super::*;
+
+    /// Held by tests which change the working directory, which is shared
+    /// by every test.
+    static WORKING_DIR: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn parses_tags_and_ranges() {
+        let tag = |tag: &str| Ok(ServerVersion::Tag(tag.into()));
+        let range = |req: &str| Ok(ServerVersion::Range(VersionReq::parse(req).unwrap()));
+        assert_eq!(parse_version("v0.2.5"), tag("v0.2.5"));
+        assert_eq!(parse_version(" 0.2.5 "), tag("v0.2.5"));
+        assert_eq!(parse_version("v0.3.0-rc.1"), tag("v0.3.0-rc.1"));
+        assert_eq!(parse_version("nightly-20240101"), tag("nightly-20240101"));
+        assert_eq!(parse_version("0.2.x"), range("0.2.x"));
+        assert_eq!(parse_version("v0.2"), range("0.2"));
+        assert_eq!(parse_version(">=0.2, <0.3"), range(">=0.2, <0.3"));
+        assert_eq!(parse_version("~0.2.5"), range("~0.2.5"));
+    }
+
+    #[test]
+    fn rejects_invalid_and_unsupported_versions() {
+        for version in ["", "  ", "v0.2.5; rm -rf /", "../v0.2.5", "latest release"] {
+            let err = parse_version(version).unwrap_err();
+            assert!(
+                err.starts_with("invalid `server_version`"),
+                "{version}: {err}"
+            );
+        }
+        let err = parse_version("v0.1.9").unwrap_err();
+        assert!(
+            err.contains("0.1.9 is older than the minimum supported 0.2.0"),
+            "{err}"
+        );
+        assert!(err.ends_with("upgrade or remove the pin"), "{err}");
+        assert!(parse_version(&format!("v{MIN_SUPPORTED_VERSION}")).is_ok());
+    }
+
+    /// Returns a new empty directory under the system's temporary
+    /// directory, named after `test`.
+    fn temp_dir(test: &str) -> String {
+        let dir = env::temp_dir().join(format!("zed-mcp-grafana-{test}"));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn binary_names_end_in_exe_on_windows() {
+        assert_eq!(
+            binary_file_name(zed::Os::Windows, BINARY_NAME),
+            "mcp-grafana.exe"
+        );
+        assert_eq!(binary_file_name(zed::Os::Mac, BINARY_NAME), "mcp-grafana");
+        assert_eq!(
+            binary_file_name(zed::Os::Linux, "mcp-grafana-fork"),
+            "mcp-grafana-fork"
+        );
+    }
+
+    #[test]
+    fn finds_windows_binaries() {
+        let dir = temp_dir("finds-windows-binaries");
+        assert_eq!(find_binary(&dir, zed::Os::Windows), None);
+        fs::write(format!("{dir}/mcp-grafana"), "").unwrap();
+        fs::write(format!("{dir}/README.md"), "").unwrap();
+        assert_eq!(find_binary(&dir, zed::Os::Windows), None);
+        fs::write(format!("{dir}/mcp-grafana_windows_amd64.exe"), "").unwrap();
+        assert_eq!(
+            find_binary(&dir, zed::Os::Windows),
+            Some(format!("{dir}/mcp-grafana_windows_amd64.exe"))
+        );
+        fs::write(format!("{dir}/mcp-grafana.exe"), "").unwrap();
+        assert_eq!(
+            find_binary(&dir, zed::Os::Windows),
+            Some(format!("{dir}/mcp-grafana.exe"))
+        );
+        assert_eq!(
+            find_binary(&dir, zed::Os::Linux),
+            Some(format!("{dir}/mcp-grafana"))
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn recognizes_version_and_platform_dirs() {
+        let cases = [
+            (
+                "mcp-grafana-v0.2.5",
+                Some("v0.2.5"),
+                Some(Version::new(0, 2, 5)),
+            ),
+            (
+                "mcp-grafana-v1.0.0-rc.1",
+                Some("v1.0.0-rc.1"),
+                releases::parse_tag("v1.0.0-rc.1"),
+            ),
+            (
+                "mcp-grafana-nightly-20240101",
+                Some("nightly-20240101"),
+                None,
+            ),
+            ("mcp-grafana-0.2.5", Some("0.2.5"), None),
+            ("mcp-grafana", None, None),
+            ("mcp-grafanav0.2.5", None, None),
+            (".staging-v0.2.5-123", None, None),
+            ("manifest.json", None, None),
+            ("linux-x86_64", None, None),
+        ];
+        for (name, tag, version) in cases {
+            assert_eq!(tag_of_dir(name), tag, "{name}");
+            assert_eq!(version_of_dir(name), version, "{name}");
+        }
+        for name in [
+            "darwin-arm64",
+            "linux-x86_64",
+            "linux-i386",
+            "windows-x86_64",
+        ] {
+            assert!(is_platform_dir(name), "{name}");
+        }
+        for name in ["darwin", "linux-amd64", "Linux-x86_64", "nightly", "repos"] {
+            assert!(!is_platform_dir(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_versions() {
+        let installed = |names: &[&str]| -> Vec<(Version, String)> {
+            names
+                .iter()
+                .map(|name| {
+                    (
+                        version_of_dir(name).unwrap(),
+                        format!("linux-x86_64/{name}"),
+                    )
+                })
+                .collect()
+        };
+        let outdated = |names: &[&str], current: &str, keep: usize| -> Vec<String> {
+            outdated_versions(installed(names), &format!("linux-x86_64/{current}"), keep)
+                .into_iter()
+                .map(|(_, dir)| dir.trim_start_matches("linux-x86_64/").to_string())
+                .collect()
+        };
+        let names = [
+            "mcp-grafana-v0.9.1",
+            "mcp-grafana-v0.2.5",
+            "mcp-grafana-v0.10.0",
+            "mcp-grafana-v0.10.0-rc.1",
+        ];
+        assert_eq!(
+            outdated(&names, "mcp-grafana-v0.10.0", 2),
+            ["mcp-grafana-v0.9.1", "mcp-grafana-v0.2.5"]
+        );
+        assert_eq!(
+            outdated(&names, "mcp-grafana-v0.10.0", 1),
+            [
+                "mcp-grafana-v0.10.0-rc.1",
+                "mcp-grafana-v0.9.1",
+                "mcp-grafana-v0.2.5"
+            ]
+        );
+        // The current directory is kept even when pinned to an old release.
+        assert_eq!(
+            outdated(&names, "mcp-grafana-v0.2.5", 1),
+            ["mcp-grafana-v0.10.0-rc.1", "mcp-grafana-v0.9.1"]
+        );
+        assert!(outdated(&names, "mcp-grafana-v0.10.0", 4).is_empty());
+        assert_eq!(
+            outdated(&names, "mcp-grafana-v0.10.0", 0),
+            [
+                "mcp-grafana-v0.10.0-rc.1",
+                "mcp-grafana-v0.9.1",
+                "mcp-grafana-v0.2.5"
+            ]
+        );
+    }
+
+    #[test]
+    fn orders_directories_of_the_same_version_by_name() {
+        let installed = |dirs: &[&str]| -> Vec<(Version, String)> {
+            dirs.iter()
+                .map(|dir| (Version::new(0, 2, 5), dir.to_string()))
+                .collect()
+        };
+        let mut dirs = [
+            "b/mcp-grafana-v0.2.5",
+            "a/mcp-grafana-v0.2.5",
+            "c/mcp-grafana-v0.2.5",
+        ];
+        let expected = ["b/mcp-grafana-v0.2.5", "c/mcp-grafana-v0.2.5"];
+        assert_eq!(
+            outdated_versions(installed(&dirs), "", 1)
+                .into_iter()
+                .map(|(_, dir)| dir)
+                .collect::<Vec<_>>(),
+            expected
+        );
+        dirs.reverse();
+        assert_eq!(
+            outdated_versions(installed(&dirs), "", 1)
+                .into_iter()
+                .map(|(_, dir)| dir)
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn prefers_native_arm64_under_rosetta() {
+        use zed::{Architecture::*, Os::*};
+
+        let cases = [
+            (Mac, X8664, None, true, true, (Aarch64, ArchReason::Rosetta)),
+            (Mac, X8664, None, false, true, (X8664, ArchReason::Detected)),
+            (Mac, X8664, None, true, false, (X8664, ArchReason::Detected)),
+            (
+                Mac,
+                Aarch64,
+                None,
+                true,
+                true,
+                (Aarch64, ArchReason::Detected),
+            ),
+            (
+                Linux,
+                X8664,
+                None,
+                true,
+                true,
+                (X8664, ArchReason::Detected),
+            ),
+            (
+                Mac,
+                X8664,
+                Some(Arch::X8664),
+                true,
+                true,
+                (X8664, ArchReason::Setting),
+            ),
+            (
+                Mac,
+                Aarch64,
+                Some(Arch::X8664),
+                true,
+                true,
+                (X8664, ArchReason::Setting),
+            ),
+            (
+                Windows,
+                X8664,
+                Some(Arch::I386),
+                true,
+                true,
+                (X86, ArchReason::Setting),
+            ),
+        ];
+        for (platform, detected, arch, prefer_native, apple_silicon, expected) in cases {
+            assert_eq!(
+                select_arch(platform, detected, arch, prefer_native, || apple_silicon),
+                expected,
+                "{platform:?} {detected:?} {arch:?} {prefer_native} {apple_silicon}"
+            );
+        }
+    }
+
+    #[test]
+    fn detects_apple_silicon_only_when_it_matters() {
+        let detect = || -> bool { panic!("detected Apple Silicon needlessly") };
+        select_arch(zed::Os::Linux, zed::Architecture::X8664, None, true, detect);
+        select_arch(zed::Os::Mac, zed::Architecture::Aarch64, None, true, detect);
+        select_arch(zed::Os::Mac, zed::Architecture::X8664, None, false, detect);
+        select_arch(
+            zed::Os::Mac,
+            zed::Architecture::X8664,
+            Some(Arch::X8664),
+            true,
+            detect,
+        );
+    }
+
+    /// Returns the default options on `platform` and `arch`, with `libc`.
+    fn options(platform: zed::Os, arch: zed::Architecture, libc: Option<Libc>) -> BinaryOptions {
+        BinaryOptions {
+            platform,
+            arch,
+            arch_reason: ArchReason::Detected,
+            storage_dir: None,
+            platform_dir: platform_dir(platform, arch),
+            libc,
+            version: ServerVersion::Latest,
+            binary_path: None,
+            archive_path: None,
+            archive_version: None,
+            dev: None,
+            prefer_system_binary: false,
+            build_from_source: false,
+            channel: Channel::Stable,
+            repo: Repo::new("github_repo", REPO_NAME, None).unwrap(),
+            binary_name: BINARY_NAME.into(),
+            asset_name_template: None,
+            nightly_tag_pattern: DEFAULT_NIGHTLY_TAG_PATTERN.into(),
+            allow_prerelease: false,
+            auto_update: true,
+            github_token: None,
+            download_url_template: None,
+            release_check_interval_seconds: 0,
+            keep_versions: 1,
+            purge_on_next_start: false,
+            force_reinstall: false,
+            notify_new_releases: false,
+            strict_latest: false,
+            verify_checksum: true,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn selects_the_libc_on_linux_only() {
+        let detect = || Libc::Musl;
+        assert_eq!(select_libc(zed::Os::Linux, None, detect), Some(Libc::Musl));
+        assert_eq!(
+            select_libc(zed::Os::Linux, None, || Libc::Gnu),
+            Some(Libc::Gnu)
+        );
+        let no_detect = || -> Libc { panic!("detected the libc needlessly") };
+        assert_eq!(
+            select_libc(zed::Os::Linux, Some(Libc::Gnu), no_detect),
+            Some(Libc::Gnu)
+        );
+        assert_eq!(
+            select_libc(zed::Os::Linux, Some(Libc::Musl), no_detect),
+            Some(Libc::Musl)
+        );
+        assert_eq!(select_libc(zed::Os::Mac, Some(Libc::Musl), no_detect), None);
+        assert_eq!(select_libc(zed::Os::Windows, None, no_detect), None);
+    }
+
+    #[test]
+    fn prefers_musl_builds_on_musl() {
+        let musl = asset_names(
+            &options(zed::Os::Linux, zed::Architecture::X8664, Some(Libc::Musl)),
+            "v0.2.5",
+        );
+        assert_eq!(musl[0], "mcp-grafana_Linux_x86_64_musl.tar.gz");
+        let first_glibc = musl
+            .iter()
+            .position(|name| !name.contains(MUSL_SUFFIX))
+            .unwrap();
+        assert!(
+            musl[first_glibc..]
+                .iter()
+                .all(|name| !name.contains(MUSL_SUFFIX))
+        );
+        assert_eq!(musl[first_glibc], "mcp-grafana_Linux_x86_64.tar.gz");
+
+        let gnu = asset_names(
+            &options(zed::Os::Linux, zed::Architecture::X8664, Some(Libc::Gnu)),
+            "v0.2.5",
+        );
+        assert_eq!(gnu, musl[first_glibc..]);
+    }
+
+    /// Returns release `tag` with assets named `assets`.
+    fn release(tag: &str, assets: &[&str]) -> zed::GithubRelease {
+        zed::GithubRelease {
+            version: tag.into(),
+            assets: assets
+                .iter()
+                .map(|name| zed::GithubReleaseAsset {
+                    name: name.to_string(),
+                    download_url: format!("https://example.com/{name}"),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn lists_candidate_asset_names_in_order() {
+        use zed::{Architecture::*, Os::*};
+
+        let cases: [(zed::Os, zed::Architecture, &[&str]); 4] = [
+            (
+                Mac,
+                Aarch64,
+                &[
+                    "mcp-grafana_Darwin_arm64.tar.gz",
+                    "mcp-grafana_darwin_arm64.tar.gz",
+                    "mcp-grafana_Darwin_arm64.zip",
+                    "mcp-grafana_darwin_arm64.zip",
+                ],
+            ),
+            (
+                Linux,
+                X8664,
+                &[
+                    "mcp-grafana_Linux_x86_64.tar.gz",
+                    "mcp-grafana_Linux_amd64.tar.gz",
+                    "mcp-grafana_linux_x86_64.tar.gz",
+                    "mcp-grafana_linux_amd64.tar.gz",
+                    "mcp-grafana_Linux_x86_64.zip",
+                    "mcp-grafana_Linux_amd64.zip",
+                    "mcp-grafana_linux_x86_64.zip",
+                    "mcp-grafana_linux_amd64.zip",
+                ],
+            ),
+            (
+                Linux,
+                X86,
+                &[
+                    "mcp-grafana_Linux_i386.tar.gz",
+                    "mcp-grafana_Linux_386.tar.gz",
+                    "mcp-grafana_linux_i386.tar.gz",
+                    "mcp-grafana_linux_386.tar.gz",
+                    "mcp-grafana_Linux_i386.zip",
+                    "mcp-grafana_Linux_386.zip",
+                    "mcp-grafana_linux_i386.zip",
+                    "mcp-grafana_linux_386.zip",
+                ],
+            ),
+            (
+                Windows,
+                X8664,
+                &[
+                    "mcp-grafana_Windows_x86_64.zip",
+                    "mcp-grafana_Windows_amd64.zip",
+                    "mcp-grafana_windows_x86_64.zip",
+                    "mcp-grafana_windows_amd64.zip",
+                    "mcp-grafana_Windows_x86_64.tar.gz",
+                    "mcp-grafana_Windows_amd64.tar.gz",
+                    "mcp-grafana_windows_x86_64.tar.gz",
+                    "mcp-grafana_windows_amd64.tar.gz",
+                ],
+            ),
+        ];
+        for (platform, arch, expected) in cases {
+            let libc = (platform == Linux).then_some(Libc::Gnu);
+            assert_eq!(
+                asset_names(&options(platform, arch, libc), "v0.2.5"),
+                expected,
+                "{platform:?} {arch:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn finds_the_first_candidate_the_release_has() {
+        let options = options(zed::Os::Linux, zed::Architecture::X8664, Some(Libc::Gnu));
+        let old = release(
+            "v0.1.0",
+            &[
+                "checksums.txt",
+                "mcp-grafana_linux_amd64.zip",
+                "mcp-grafana_linux_amd64.tar.gz",
+            ],
+        );
+        assert_eq!(
+            find_asset(&old, &options).map(|asset| asset.name.as_str()),
+            Some("mcp-grafana_linux_amd64.tar.gz")
+        );
+
+        let renamed = release("v9.0.0", &["checksums.txt", "grafana-mcp-linux-x64.tgz"]);
+        assert!(find_asset(&renamed, &options).is_none());
+        let description = describe_assets(&renamed, &options);
+        assert!(
+            description.starts_with(
+                "has none of the assets mcp-grafana_Linux_x86_64.tar.gz, \
+                 mcp-grafana_Linux_amd64.tar.gz,"
+            ),
+            "{description}"
+        );
+        assert!(
+            description.ends_with("; it has checksums.txt, grafana-mcp-linux-x64.tgz"),
+            "{description}"
+        );
+        assert!(describe_assets(&release("v9.0.0", &[]), &options).ends_with("; it has no assets"));
+    }
+
+    #[test]
+    fn renders_asset_name_templates() {
+        use zed::{Architecture::*, Os::*};
+
+        let template = "mcp-grafana-{version}-{goos}-{goarch}.{ext}";
+        let cases = [
+            (Mac, Aarch64, "mcp-grafana-0.2.5-darwin-arm64.tar.gz"),
+            (Linux, X8664, "mcp-grafana-0.2.5-linux-amd64.tar.gz"),
+            (Linux, X86, "mcp-grafana-0.2.5-linux-386.tar.gz"),
+            (Windows, X8664, "mcp-grafana-0.2.5-windows-amd64.zip"),
+        ];
+        for (platform, arch, expected) in cases {
+            assert_eq!(
+                AssetName::new("v0.2.5", platform, arch).render(template),
+                expected
+            );
+        }
+        let values = AssetName::new("v0.2.5", Mac, X8664);
+        assert_eq!(values.render("{tag}/{os}_{arch}"), "v0.2.5/Darwin_x86_64");
+        assert_eq!(
+            values.render(ASSET_NAME_TEMPLATE),
+            "mcp-grafana_Darwin_x86_64.tar.gz"
+        );
+        // Tags without a `v` are their own version.
+        let values = AssetName::new("nightly-1", Linux, X8664);
+        assert_eq!(values.render("{tag} {version}"), "nightly-1 nightly-1");
+    }
+
+    #[test]
+    fn checks_asset_name_templates() {
+        for template in [
+            "mcp-grafana-{version}-{goos}-{goarch}.tgz",
+            " grafana.{ext} ",
+        ] {
+            assert_eq!(check_asset_name_template(template), Ok(()), "{template}");
+        }
+        for template in [
+            "",
+            "  ",
+            "mcp-grafana-{platform}.tgz",
+            "mcp-grafana-{version",
+        ] {
+            let err = check_asset_name_template(template).unwrap_err();
+            assert!(
+                err.starts_with("invalid `asset_name_template`"),
+                "{template}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn asset_name_templates_replace_the_candidates() {
+        let mut options = options(zed::Os::Linux, zed::Architecture::Aarch64, Some(Libc::Musl));
+        options.asset_name_template = Some("mcp-grafana-{version}-{goos}-{goarch}.tgz".into());
+        assert_eq!(
+            asset_names(&options, "v0.2.5"),
+            ["mcp-grafana-0.2.5-linux-arm64.tgz"]
+        );
+        let release = release("v0.2.5", &["mcp-grafana_Linux_arm64.tar.gz"]);
+        assert!(find_asset(&release, &options).is_none());
+        assert_eq!(
+            describe_assets(&release, &options),
+            "has none of the assets mcp-grafana-0.2.5-linux-arm64.tgz; it has \
+             mcp-grafana_Linux_arm64.tar.gz"
+        );
+    }
+
+    /// Runs `test` in a new empty working directory, named after `name`,
+    /// as the extension runs in its own.
+    fn in_working_dir(name: &str, test: impl FnOnce()) {
+        let _guard = WORKING_DIR.lock().unwrap_or_else(|err| err.into_inner());
+        let previous = env::current_dir().unwrap();
+        let dir = temp_dir(name);
+        env::set_current_dir(&dir).unwrap();
+        // An empty manifest keeps it from being regenerated by running the
+        // binaries, which are stand-ins.
+        Manifest::default().save();
+        test();
+        env::set_current_dir(previous).unwrap();
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Installs a stand-in binary of release `tag` in the platform
+    /// directory of `options`, recording it in the manifest, and returns
+    /// its path.
+    fn install_stand_in(options: &BinaryOptions, tag: &str) -> String {
+        let version_dir = version_dir(&options.platform_dir, tag);
+        fs::create_dir_all(&version_dir).unwrap();
+        let binary_path = format!("{version_dir}/{BINARY_NAME}");
+        fs::write(&binary_path, format!("mcp-grafana {tag}")).unwrap();
+        let mut manifest = load_manifest(options);
+        manifest
+            .record(&version_dir, tag, &binary_path, None)
+            .unwrap();
+        manifest.save();
+        binary_path
+    }
+
+    #[test]
+    fn caches_binaries_by_context_server() {
+        in_working_dir("caches-binaries-by-context-server", || {
+            let pinned = |tag: &str| {
+                let mut options = options(zed::Os::Linux, zed::Architecture::X8664, None);
+                options.version = ServerVersion::Tag(tag.into());
+                options
+            };
+            let (prod, staging) = (pinned("v0.2.5"), pinned("v0.3.0"));
+            let prod_binary = install_stand_in(&prod, "v0.2.5");
+            let staging_binary = install_stand_in(&staging, "v0.3.0");
+
+            let mut cache = BinaryCache::default();
+            assert_eq!(cache.get("grafana-prod", &prod), Ok(prod_binary.clone()));
+            assert_eq!(
+                cache.get("grafana-staging", &staging),
+                Ok(staging_binary.clone())
+            );
+            assert_eq!(cache.get("grafana-prod", &prod), Ok(prod_binary.clone()));
+            assert_eq!(cache.entries.len(), 2);
+            assert_eq!(
+                cache.entries["grafana-prod"],
+                (prod.clone(), prod_binary.clone())
+            );
+            assert_eq!(
+                cache.entries["grafana-staging"],
+                (staging, staging_binary.clone())
+            );
+
+            // Changing a context server's options replaces only its entry.
+            assert_eq!(
+                cache.get("grafana-prod", &pinned("v0.3.0")),
+                Ok(staging_binary.clone())
+            );
+            assert_eq!(cache.entries["grafana-prod"].1, staging_binary);
+            let manifest = load_manifest(&prod);
+            assert!(manifest.is_active_elsewhere(
+                &version_dir(&prod.platform_dir, "v0.3.0"),
+                "grafana-staging"
+            ));
+            assert!(!manifest.is_active_elsewhere(
+                &version_dir(&prod.platform_dir, "v0.2.5"),
+                "grafana-staging"
+            ));
+        });
+    }
+
+    /// Stands in for GitHub, serving `release`, and staging a binary
+    /// unless `stage_error` is set.
+    struct FakeSource {
+        release: Result<zed::GithubRelease>,
+        stage_error: Option<String>,
+    }
+
+    impl Source for FakeSource {
+        fn latest(&self, _: &Repo, _: bool, _: Option<&Secret>) -> Result<zed::GithubRelease> {
+            self.release.clone()
+        }
+
+        fn by_tag(&self, _: &Repo, tag: &str, _: Option<&Secret>) -> Result<zed::GithubRelease> {
+            let release = self.release.clone()?;
+            if release.version != tag {
+                return Err(format!("no release '{tag}'"));
+            }
+            Ok(release)
+        }
+
+        fn list(&self, _: &Repo, _: Option<&Secret>) -> Result<Vec<zed::GithubRelease>> {
+            self.release.clone().map(|release| vec![release])
+        }
+
+        fn stage(
+            &self,
+            _: &BinaryOptions,
+            release: &zed::GithubRelease,
+            _: &zed::GithubReleaseAsset,
+            staging_dir: &str,
+        ) -> Result<()> {
+            if let Some(err) = &self.stage_error {
+                return Err(err.clone());
+            }
+            fs::create_dir_all(staging_dir).unwrap();
+            fs::write(
+                format!("{staging_dir}/{BINARY_NAME}"),
+                format!("mcp-grafana {}", release.version),
+            )
+            .unwrap();
+            Ok(())
+        }
+    }
+
+    /// Records the statuses reported to it.
+    #[derive(Default)]
+    struct RecordingReporter(RefCell<Vec<Status>>);
+
+    impl Reporter for RecordingReporter {
+        fn report(&self, status: Status) {
+            self.0.borrow_mut().push(status);
+        }
+    }
+
+    /// Installs with `source` for `options`, returning the result along
+    /// with the statuses reported.
+    fn install_from(source: &FakeSource, options: &BinaryOptions) -> (Result<String>, Vec<Status>) {
+        let reporter = RecordingReporter::default();
+        let result = install("grafana", options, false, source, &reporter);
+        (result, reporter.0.into_inner())
+    }
+
+    #[test]
+    fn reports_each_phase_of_installing() {
+        in_working_dir("reports-each-phase-of-installing", || {
+            let mut options = options(zed::Os::Linux, zed::Architecture::X8664, Some(Libc::Gnu));
+            options.version = ServerVersion::Tag("v0.2.5".into());
+            let source = FakeSource {
+                release: Ok(release(
+                    "v0.2.5",
+                    &["checksums.txt", "mcp-grafana_Linux_x86_64.tar.gz"],
+                )),
+                stage_error: None,
+            };
+            let (result, statuses) = install_from(&source, &options);
+            assert_eq!(
+                result,
+                Ok(format!("linux-x86_64/mcp-grafana-v0.2.5/{BINARY_NAME}"))
+            );
+            assert_eq!(
+                statuses,
+                [
+                    Status::CheckingForRelease,
+                    Status::Downloading {
+                        tag: "v0.2.5".into(),
+                        asset: "mcp-grafana_Linux_x86_64.tar.gz".into(),
+                        platform: platform_description(&options),
+                    },
+                    Status::Installed {
+                        tag: "v0.2.5".into(),
+                        size: "mcp-grafana v0.2.5".len() as u64,
+                    },
+                ]
+            );
+
+            // The installed release is used without reporting anything.
+            let (result, statuses) = install_from(&source, &options);
+            assert!(result.is_ok());
+            assert_eq!(statuses, []);
+        });
+    }
+
+    #[test]
+    fn reports_no_further_phases_after_failing() {
+        in_working_dir("reports-no-further-phases-after-failing", || {
+            let mut options = options(zed::Os::Linux, zed::Architecture::X8664, Some(Libc::Gnu));
+            options.version = ServerVersion::Tag("v0.2.5".into());
+            let source = FakeSource {
+                release: Ok(release("v0.2.5", &["mcp-grafana_Linux_x86_64.tar.gz"])),
+                stage_error: Some("connection reset".into()),
+            };
+            let (result, statuses) = install_from(&source, &options);
+            assert_eq!(result, Err("connection reset".into()));
+            assert!(matches!(
+                statuses.as_slice(),
+                [Status::CheckingForRelease, Status::Downloading { .. }]
+            ));
+            assert!(
+                fs::read_dir(&options.platform_dir)
+                    .unwrap()
+                    .next()
+                    .is_none()
+            );
+
+            options.version = ServerVersion::Latest;
+            let source = FakeSource {
+                release: Err("failed to look up the latest release".into()),
+                stage_error: None,
+            };
+            let (result, statuses) = install_from(&source, &options);
+            let err = result.unwrap_err();
+            assert!(
+                err.ends_with("no release of the server is installed to fall back to"),
+                "{err}"
+            );
+            assert_eq!(statuses, [Status::CheckingForRelease]);
+        });
+    }
+
+    #[test]
+    fn checks_storage_dirs() {
+        let storage_dir = |dir: &str| {
+            storage_dir(&crate::settings::parse(json!({ "storage_dir": dir })).unwrap())
+        };
+        assert_eq!(
+            storage_dir("/var/cache/mcp-grafana/"),
+            Ok(Some("/var/cache/mcp-grafana".into()))
+        );
+        let home = env::var("HOME").unwrap();
+        assert_eq!(
+            storage_dir("~/.cache/mcp-grafana"),
+            Ok(Some(format!("{home}/.cache/mcp-grafana")))
+        );
+        let err = storage_dir("cache").unwrap_err();
+        assert!(
+            err.starts_with("`storage_dir` must be an absolute path"),
+            "{err}"
+        );
+        assert_eq!(in_storage(None, "linux-x86_64"), "linux-x86_64");
+        assert_eq!(
+            in_storage(Some("/cache"), "linux-x86_64"),
+            "/cache/linux-x86_64"
+        );
+        assert_eq!(in_storage(Some("/cache"), "."), "/cache");
+    }
+
+    #[test]
+    fn installs_into_and_purges_the_storage_dir() {
+        in_working_dir("installs-into-the-storage-dir", || {
+            let storage = temp_dir("storage-dir");
+            let mut options = options(zed::Os::Linux, zed::Architecture::X8664, Some(Libc::Gnu));
+            options.version = ServerVersion::Tag("v0.2.5".into());
+            options.storage_dir = Some(storage.clone());
+            options.platform_dir = in_storage(Some(&storage), "linux-x86_64");
+            let source = FakeSource {
+                release: Ok(release("v0.2.5", &["mcp-grafana_Linux_x86_64.tar.gz"])),
+                stage_error: None,
+            };
+            let (result, _) = install_from(&source, &options);
+            assert_eq!(
+                result,
+                Ok(format!(
+                    "{storage}/linux-x86_64/mcp-grafana-v0.2.5/{BINARY_NAME}"
+                ))
+            );
+            assert!(fs::metadata("linux-x86_64").is_err());
+            assert_eq!(
+                platform_dirs(options.storage_dir.as_deref()),
+                [format!("{storage}/linux-x86_64")]
+            );
+
+            purge(&options);
+            assert!(fs::read_dir(&storage).unwrap().next().is_none());
+            fs::remove_dir_all(&storage).ok();
+        });
+    }
+
+    #[test]
+    fn legacy_dirs_are_migrated_once() {
+        in_working_dir("legacy-dirs-are-migrated-once", || {
+            let options = options(zed::Os::Linux, zed::Architecture::X8664, None);
+            migrate_legacy_dirs(&options);
+            assert!(fs::metadata(".migrated-linux-x86_64").is_ok());
+
+            // Were it checked again, the stand-in would be run.
+            fs::create_dir_all("mcp-grafana-v0.2.5").unwrap();
+            fs::write("mcp-grafana-v0.2.5/mcp-grafana", "mcp-grafana v0.2.5").unwrap();
+            migrate_legacy_dirs(&options);
+            assert!(fs::metadata("mcp-grafana-v0.2.5/mcp-grafana").is_ok());
+        });
+    }
+
+    #[test]
+    fn regenerated_manifests_hold_the_platforms_releases() {
+        in_working_dir("regenerated-manifests-hold-the-platforms-releases", || {
+            let linux = options(zed::Os::Linux, zed::Architecture::X8664, None);
+            let mac = options(zed::Os::Mac, zed::Architecture::Aarch64, None);
+            install_stand_in(&linux, "v0.2.5");
+            install_stand_in(&mac, "v0.3.0");
+            assert!(manifest::clear());
+
+            let manifest = load_manifest(&linux);
+            let installed = |options: &BinaryOptions| {
+                installed_versions(&manifest, &options.platform_dir)
+                    .into_iter()
+                    .map(|(version, _)| version.to_string())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(installed(&linux), ["0.2.5"]);
+            assert!(installed(&mac).is_empty());
+        });
+    }
+}
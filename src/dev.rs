@@ -0,0 +1,134 @@
+//! Running the server built from a local checkout of mcp-grafana, for
+//! working on the server itself.
+
+use std::fs;
+
+use serde::Deserialize;
+use zed_extension_api::{self as zed, Result};
+
+use crate::{log, paths, settings::GrafanaContextServerSettings, subprocess};
+
+/// The directory of the extension's working directory the checkout is
+/// built into.
+const DEV_DIR: &str = "dev";
+
+/// The package of the server within the checkout.
+const PACKAGE: &str = "./cmd/mcp-grafana";
+
+/// How long building the checkout may take.
+const BUILD_TIMEOUT_SECS: u64 = 600;
+
+/// When to build the checkout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Rebuild {
+    /// Every time the context server starts.
+    #[default]
+    Always,
+    /// Only if it hasn't been built yet, or never if `dev_binary` is set.
+    Never,
+}
+
+/// A local checkout of the server to run instead of a release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Checkout {
+    /// The root of the checkout.
+    path: String,
+    /// A binary built from the checkout to run without building it.
+    binary: Option<String>,
+    rebuild: Rebuild,
+}
+
+impl Checkout {
+    /// Returns the checkout given by `dev_path`, if it is set.
+    pub(crate) fn new(settings: &GrafanaContextServerSettings) -> Result<Option<Self>> {
+        let Some(path) = &settings.dev_path else {
+            if settings.dev_binary.is_some() {
+                return Err("`dev_binary` is only used with `dev_path`".into());
+            }
+            return Ok(None);
+        };
+        let conflict = [
+            ("binary_path", settings.binary_path.is_some()),
+            ("archive_path", settings.archive_path.is_some()),
+        ]
+        .into_iter()
+        .find_map(|(setting, set)| set.then_some(setting));
+        if let Some(setting) = conflict {
+            return Err(format!(
+                "`{setting}` can't be combined with `dev_path`, which runs the server built from \
+                 a local checkout instead"
+            ));
+        }
+        let path = paths::expand_home("dev_path", path)?;
+        let is_dir = fs::metadata(&path).is_ok_and(|metadata| metadata.is_dir());
+        if !is_dir {
+            return Err(format!("`dev_path` '{path}' is not a directory"));
+        }
+        Ok(Some(Self {
+            path,
+            binary: settings
+                .dev_binary
+                .as_deref()
+                .map(|binary| paths::expand_home("dev_binary", binary))
+                .transpose()?,
+            rebuild: settings.dev_rebuild,
+        }))
+    }
+
+    /// Returns the path of the server binary to run for the checkout,
+    /// building it first unless `dev_rebuild` is `"never"`.
+    pub(crate) fn binary(&self, platform: zed::Os) -> Result<String> {
+        let output = match platform {
+            zed::Os::Windows => format!("{DEV_DIR}/mcp-grafana.exe"),
+            zed::Os::Mac | zed::Os::Linux => format!("{DEV_DIR}/mcp-grafana"),
+        };
+        if self.rebuild == Rebuild::Never {
+            if let Some(binary) = &self.binary {
+                paths::check_executable_file("dev_binary", binary)?;
+                return Ok(binary.clone());
+            }
+            if fs::metadata(&output).is_ok() {
+                return Ok(output);
+            }
+        }
+        self.build(platform, &output)?;
+        Ok(output)
+    }
+
+    /// Builds the checkout into `output` with `go build`.
+    fn build(&self, platform: zed::Os, output: &str) -> Result<()> {
+        let go = subprocess::find_on_path("go", platform).ok_or_else(|| {
+            "`dev_path` is set, but go isn't on PATH to build it; set `dev_binary` and \
+             `\"dev_rebuild\": \"never\"` to run a binary you built yourself"
+                .to_string()
+        })?;
+        fs::create_dir_all(DEV_DIR)
+            .map_err(|err| format!("failed to create directory '{DEV_DIR}': {err}"))?;
+        let output = paths::in_work_dir(output)?;
+        let path = &self.path;
+        // `go build` has to run in the checkout, which the extension API
+        // can't set as the working directory.
+        let argv = match platform {
+            zed::Os::Windows => vec![
+                "cmd".into(),
+                "/C".into(),
+                format!("cd /d \"{path}\" && \"{go}\" build -o \"{output}\" {PACKAGE}"),
+            ],
+            zed::Os::Mac | zed::Os::Linux => vec![
+                "sh".into(),
+                "-c".into(),
+                r#"cd "$1" && exec "$2" build -o "$3" "$4""#.into(),
+                "sh".into(),
+                path.clone(),
+                go,
+                output.clone(),
+                PACKAGE.into(),
+            ],
+        };
+        log(format!("building the server from '{path}'"));
+        subprocess::run("go build", argv, BUILD_TIMEOUT_SECS)
+            .map_err(|err| format!("failed to build `dev_path` '{path}': {err}"))?;
+        Ok(())
+    }
+}
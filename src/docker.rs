@@ -0,0 +1,146 @@
+//! Running the server from its container image instead of a downloaded
+//! binary.
+
+use serde::Deserialize;
+use zed_extension_api::{self as zed, Command, Result};
+
+use crate::{log, releases, settings::GrafanaContextServerSettings, subprocess};
+
+/// The official container image of the server.
+const IMAGE: &str = "mcp/grafana";
+
+/// The tag of [`IMAGE`] run when the latest release can't be looked up.
+const FALLBACK_TAG: &str = "latest";
+
+/// How the server is run.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Runtime {
+    /// Run a downloaded or configured binary.
+    #[default]
+    Binary,
+    /// Run the server's container image with `docker run`.
+    Docker,
+}
+
+/// Returns the path of `docker` on `PATH`.
+pub(crate) fn find() -> Result<String> {
+    let (platform, _) = zed::current_platform();
+    subprocess::find_on_path("docker", platform).ok_or_else(|| {
+        "`runtime` is \"docker\", but docker isn't on PATH; install Docker, or remove `runtime` \
+         to download the server instead"
+            .into()
+    })
+}
+
+/// Returns the container image to run, which is `docker_image` if set,
+/// or otherwise the official image of the release pinned by
+/// `server_version`, or of the release `latest_tag` returns.
+///
+/// If the latest release can't be looked up, the image's `latest` tag is
+/// run instead.
+pub(crate) fn image(
+    settings: &GrafanaContextServerSettings,
+    latest_tag: impl FnOnce() -> Result<String>,
+) -> Result<String> {
+    let binary_setting = [
+        ("binary_path", settings.binary_path.is_some()),
+        ("archive_path", settings.archive_path.is_some()),
+        ("dev_path", settings.dev_path.is_some()),
+        ("prefer_system_binary", settings.prefer_system_binary),
+    ]
+    .into_iter()
+    .find_map(|(setting, set)| set.then_some(setting));
+    if let Some(setting) = binary_setting {
+        return Err(format!(
+            "`{setting}` can't be combined with `\"runtime\": \"docker\"`, which runs the \
+             server's container image instead"
+        ));
+    }
+    if let Some(image) = &settings.docker_image {
+        if image.trim().is_empty() {
+            return Err("`docker_image` must not be empty".into());
+        }
+        return Ok(image.trim().into());
+    }
+    let version = match settings.server_version.as_deref() {
+        None => match latest_tag() {
+            Ok(tag) => releases::parse_tag(&tag).map_or(tag, |version| version.to_string()),
+            Err(err) => {
+                log(format!(
+                    "running {IMAGE}:{FALLBACK_TAG}, since the latest release couldn't be looked \
+                     up: {err}"
+                ));
+                FALLBACK_TAG.into()
+            }
+        },
+        Some(version) => releases::parse_tag(version.trim())
+            .ok_or_else(|| {
+                format!(
+                    "`server_version` '{version}' must be a single release, such as 0.2.5, with \
+                     `\"runtime\": \"docker\"`; set `docker_image` to choose another image"
+                )
+            })?
+            .to_string(),
+    };
+    Ok(format!("{IMAGE}:{version}"))
+}
+
+/// Returns the command running `args` of the server in the container
+/// `image` with `docker`, passing it `env` and the extra `docker_args`.
+///
+/// The variables are passed by name only, so that their values, which
+/// include credentials, don't appear in the command line.
+pub(crate) fn command(
+    docker: String,
+    image: &str,
+    docker_args: &[String],
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+) -> Command {
+    let mut docker_run: Vec<String> = vec!["run".into(), "-i".into(), "--rm".into()];
+    for (key, _) in &env {
+        docker_run.extend(["-e".into(), key.clone()]);
+    }
+    docker_run.extend(docker_args.iter().cloned());
+    docker_run.push(image.into());
+    docker_run.extend(args);
+    Command {
+        command: docker,
+        args: docker_run,
+        env,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zed_extension_api::serde_json::json;
+
+    use super::*;
+    use crate::settings;
+
+    #[test]
+    fn images_follow_the_pinned_or_latest_release() {
+        let image_for = |value, latest: Result<String>| {
+            let settings = settings::parse(value).unwrap();
+            image(&settings, || latest)
+        };
+        let unused = || Err("unused".to_string());
+        assert_eq!(
+            image_for(json!({ "server_version": "v0.3.0" }), unused()),
+            Ok("mcp/grafana:0.3.0".into())
+        );
+        assert_eq!(
+            image_for(json!({}), Ok("v0.4.1".into())),
+            Ok("mcp/grafana:0.4.1".into())
+        );
+        assert_eq!(
+            image_for(json!({}), Err("offline".into())),
+            Ok("mcp/grafana:latest".into())
+        );
+        assert_eq!(
+            image_for(json!({ "docker_image": " my/grafana:dev " }), unused()),
+            Ok("my/grafana:dev".into())
+        );
+    }
+}
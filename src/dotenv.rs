@@ -0,0 +1,157 @@
+//! A small parser for dotenv-format files.
+//!
+//! Supports `KEY=value` lines, blank lines, `#` comments, an optional
+//! `export` prefix, and single- or double-quoted values. Double-quoted
+//! values may contain `\n`, `\t`, `\"` and `\\` escapes; single-quoted
+//! values are taken literally.
+
+/// Parses the contents of a dotenv file into key-value pairs, in the order
+/// they appear.
+///
+/// Errors include the (1-based) line number of the offending line.
+pub(crate) fn parse(contents: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line
+            .strip_prefix("export")
+            .filter(|rest| rest.starts_with(char::is_whitespace))
+            .map_or(line, str::trim_start);
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {line_number}: expected `KEY=value`"));
+        };
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err(format!("line {line_number}: invalid key '{key}'"));
+        }
+        let value =
+            parse_value(value.trim_start()).map_err(|err| format!("line {line_number}: {err}"))?;
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    let mut chars = value.chars();
+    let (quoted, rest) = match chars.next() {
+        Some('"') => {
+            let mut parsed = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => parsed.push('\n'),
+                        Some('t') => parsed.push('\t'),
+                        Some('r') => parsed.push('\r'),
+                        Some(c @ ('"' | '\\' | '$')) => parsed.push(c),
+                        Some(c) => {
+                            parsed.push('\\');
+                            parsed.push(c);
+                        }
+                        None => return Err("unterminated double-quoted value".into()),
+                    },
+                    Some(c) => parsed.push(c),
+                    None => return Err("unterminated double-quoted value".into()),
+                }
+            }
+            (parsed, chars.as_str())
+        }
+        Some('\'') => {
+            let rest = chars.as_str();
+            let Some((parsed, rest)) = rest.split_once('\'') else {
+                return Err("unterminated single-quoted value".into());
+            };
+            (parsed.to_string(), rest)
+        }
+        _ => {
+            // Unquoted values end at an inline comment, which must be
+            // preceded by whitespace so that values like `a#b` survive.
+            let end = value
+                .char_indices()
+                .find(|&(i, c)| c == '#' && value[..i].ends_with(char::is_whitespace))
+                .map_or(value.len(), |(i, _)| i);
+            return Ok(value[..end].trim_end().to_string());
+        }
+    };
+
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!(
+            "unexpected characters after quoted value: '{rest}'"
+        ));
+    }
+    Ok(quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(contents: &str) -> Vec<(String, String)> {
+        parse(contents).unwrap()
+    }
+
+    #[test]
+    fn parses_values() {
+        let cases = [
+            ("KEY=value", "value"),
+            ("KEY = value  ", "value"),
+            ("export KEY=value", "value"),
+            ("KEY=", ""),
+            ("KEY=a#b", "a#b"),
+            ("KEY=value # comment", "value"),
+            ("KEY=\"quoted # not a comment\"", "quoted # not a comment"),
+            (
+                "KEY=\"line\\nbreak \\\"quote\\\" \\\\ \\$HOME\"",
+                "line\nbreak \"quote\" \\ $HOME",
+            ),
+            ("KEY=\"unknown \\q escape\"", "unknown \\q escape"),
+            ("KEY='single \\n $literal'", "single \\n $literal"),
+            ("KEY='value' # comment", "value"),
+            ("KEY=a=b", "a=b"),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(vars(line), [("KEY".into(), expected.into())], "{line}");
+        }
+    }
+
+    #[test]
+    fn skips_blank_lines_and_comments_in_order() {
+        let contents = "\n# comment\nGRAFANA_URL=https://grafana.example.com\n\n  # indented\nexported=1\nGRAFANA_ORG_ID=2\n";
+        assert_eq!(
+            vars(contents),
+            [
+                ("GRAFANA_URL".into(), "https://grafana.example.com".into()),
+                ("exported".into(), "1".into()),
+                ("GRAFANA_ORG_ID".into(), "2".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        let cases = [
+            ("A=1\njust text", "line 2: expected `KEY=value`"),
+            ("A=1\n\nBAD KEY=1", "line 3: invalid key 'BAD KEY'"),
+            ("=1", "line 1: invalid key ''"),
+            ("A=\"open", "line 1: unterminated double-quoted value"),
+            ("A='open", "line 1: unterminated single-quoted value"),
+            (
+                "A=\"x\" y",
+                "line 1: unexpected characters after quoted value: 'y'",
+            ),
+        ];
+        for (contents, expected) in cases {
+            assert_eq!(parse(contents).unwrap_err(), expected, "{contents:?}");
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! The environment variables consulted when resolving settings.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+};
+
+use zed_extension_api::{self as zed, Result};
+
+use crate::{dotenv, log, paths, subprocess};
+
+/// How long to wait for the login shell to print its environment.
+const SHELL_ENV_TIMEOUT_SECS: u64 = 5;
+
+/// Printed by the login shell before its environment, so that anything
+/// its startup files print is ignored.
+const SHELL_ENV_MARKER: &str = "__ZED_MCP_GRAFANA_ENV__";
+
+/// What is remembered about the user's login shell between starts of the
+/// context servers.
+#[derive(Debug, Default)]
+pub(crate) struct LoginShell {
+    /// Whether it timed out, so that it isn't waited for again on every
+    /// start for the rest of the session.
+    timed_out: bool,
+}
+
+/// The environment variables visible to the extension.
+///
+/// Variables from the process environment take precedence over those
+/// exported by the user's login shell, which in turn take precedence over
+/// those loaded from an `env_file`.
+#[derive(Debug, Default)]
+pub(crate) struct Environment {
+    shell_vars: HashMap<String, String>,
+    file_vars: HashMap<String, String>,
+    /// Whether the process environment is left out, which it only is in
+    /// tests.
+    isolated: bool,
+}
+
+impl Environment {
+    /// Returns the environment of the extension's process, plus the
+    /// `GRAFANA_*` variables from `login_shell`, if given because
+    /// `inherit_shell_env` is set, and from `env_file`, if set.
+    pub(crate) fn load(
+        env_file: Option<&str>,
+        login_shell: Option<&mut LoginShell>,
+    ) -> Result<Self> {
+        let shell_vars = login_shell.map(load_shell_vars).unwrap_or_default();
+        let Some(path) = env_file else {
+            return Ok(Self {
+                shell_vars,
+                ..Self::default()
+            });
+        };
+        paths::check_readable_file("env_file", path)?;
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read `env_file` '{path}': {err}"))?;
+        let file_vars = dotenv::parse(&contents)
+            .map_err(|err| format!("failed to parse `env_file` '{path}': {err}"))?
+            .into_iter()
+            .filter(|(key, _)| key.starts_with("GRAFANA_"))
+            .collect();
+        Ok(Self {
+            shell_vars,
+            file_vars,
+            isolated: false,
+        })
+    }
+
+    /// Returns an environment with only `vars`, as if they were exported by
+    /// the login shell, so that tests don't depend on the process
+    /// environment.
+    #[cfg(test)]
+    pub(crate) fn with_vars(vars: &[(&str, &str)]) -> Self {
+        Self {
+            shell_vars: vars
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            isolated: true,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the value of the variable `name`, if set.
+    pub(crate) fn var(&self, name: &str) -> Option<String> {
+        env::var(name)
+            .ok()
+            .filter(|_| !self.isolated)
+            .or_else(|| self.shell_vars.get(name).cloned())
+            .or_else(|| self.file_vars.get(name).cloned())
+    }
+
+    /// Returns the variables loaded from the login shell and `env_file`,
+    /// sorted by name.
+    pub(crate) fn loaded_vars(&self) -> Vec<(&str, &str)> {
+        let mut vars = BTreeMap::new();
+        for (key, value) in self.file_vars.iter().chain(&self.shell_vars) {
+            vars.insert(key.as_str(), value.as_str());
+        }
+        vars.into_iter().collect()
+    }
+}
+
+/// Returns whether `name` is a portable environment variable name, made
+/// of ASCII letters, digits and underscores and not starting with a digit.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns the `GRAFANA_*` variables exported by the user's login shell.
+///
+/// Zed doesn't expose the shell environment of the project to context
+/// servers, so this runs `$SHELL` as a login shell, which reads the same
+/// profile as a terminal would. It isn't run as an interactive shell, which
+/// may prompt or start a terminal multiplexer.
+///
+/// Failures are logged and otherwise ignored, since the shell environment
+/// is only a fallback. Once the shell times out, it isn't run again until
+/// Zed restarts.
+fn load_shell_vars(login_shell: &mut LoginShell) -> HashMap<String, String> {
+    let (platform, _) = zed::current_platform();
+    if platform == zed::Os::Windows || login_shell.timed_out {
+        return HashMap::new();
+    }
+    let script =
+        format!(r#"exec "${{SHELL:-/bin/sh}}" -l -c 'echo {SHELL_ENV_MARKER}; env' </dev/null"#);
+    let argv = vec!["sh".into(), "-c".into(), script];
+    let stdout = match subprocess::run("login shell", argv, SHELL_ENV_TIMEOUT_SECS) {
+        Ok(output) => output.stdout,
+        Err(err) if err.ends_with(&format!("timed out after {SHELL_ENV_TIMEOUT_SECS}s")) => {
+            log(format_args!(
+                "ignoring login shell environment until Zed restarts: {err}"
+            ));
+            login_shell.timed_out = true;
+            return HashMap::new();
+        }
+        Err(err) => {
+            log(format_args!("ignoring login shell environment: {err}"));
+            return HashMap::new();
+        }
+    };
+    let stdout = String::from_utf8_lossy(&stdout);
+    let Some((_, env)) = stdout.split_once(SHELL_ENV_MARKER) else {
+        log("ignoring login shell environment: the shell printed nothing");
+        return HashMap::new();
+    };
+    env.lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| key.starts_with("GRAFANA_"))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
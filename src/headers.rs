@@ -0,0 +1,55 @@
+//! Custom HTTP headers sent with every request to Grafana.
+
+use std::collections::BTreeMap;
+
+use zed_extension_api::{Result, serde_json};
+
+use crate::credentials::Secret;
+
+/// The environment variable the server reads extra headers from, as a
+/// JSON object.
+pub(crate) const ENV_VAR: &str = "GRAFANA_EXTRA_HEADERS";
+
+/// Checks that the names of `headers`, from `setting`, are valid header
+/// names, and that no two differ only by case.
+pub(crate) fn validate(setting: &str, headers: &BTreeMap<String, Secret>) -> Result<()> {
+    let mut seen = BTreeMap::new();
+    for name in headers.keys() {
+        if name.is_empty() || !name.bytes().all(is_token_char) {
+            return Err(format!("invalid header name '{name}' in `{setting}`"));
+        }
+        if let Some(other) = seen.insert(name.to_ascii_lowercase(), name) {
+            return Err(format!(
+                "`{setting}` contains both '{other}' and '{name}', which are the same header"
+            ));
+        }
+        if headers[name].expose().contains(['\r', '\n']) {
+            return Err(format!(
+                "the value of header '{name}' in `{setting}` contains a line break"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns `headers` as name and value pairs.
+pub(crate) fn pairs(headers: &BTreeMap<String, Secret>) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.clone(), value.expose().to_string()))
+        .collect()
+}
+
+/// Returns the value of [`ENV_VAR`] for `headers`.
+pub(crate) fn env_value(headers: &BTreeMap<String, Secret>) -> String {
+    let object: serde_json::Map<String, serde_json::Value> = headers
+        .iter()
+        .map(|(name, value)| (name.clone(), value.expose().into()))
+        .collect();
+    serde_json::Value::Object(object).to_string()
+}
+
+/// Returns whether `byte` may appear in a header name, per RFC 9110.
+fn is_token_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
@@ -0,0 +1,352 @@
+//! Integrity checks of downloaded release assets.
+
+use std::fs;
+
+use zed_extension_api::{
+    self as zed, Result,
+    http_client::{HttpMethod, HttpRequest, RedirectPolicy},
+};
+
+use crate::{paths, settings::GrafanaContextServerSettings, subprocess};
+
+/// The OIDC issuer of the certificates of keyless signatures made in
+/// GitHub Actions.
+const GITHUB_ACTIONS_ISSUER: &str = "https://token.actions.githubusercontent.com";
+
+/// How long `cosign` may take to verify a signature.
+const COSIGN_TIMEOUT_SECS: u64 = 60;
+
+/// How the cosign signature of a release's checksums is verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Signature {
+    /// Signed with the private key of this public key file.
+    Key(String),
+    /// Signed keylessly by this certificate identity and OIDC issuer.
+    Identity { identity: String, issuer: String },
+}
+
+impl Signature {
+    /// Returns how `settings` ask for signatures to be verified, or `None`
+    /// unless `verify_signature` is set.
+    pub(crate) fn new(settings: &GrafanaContextServerSettings) -> Result<Option<Self>> {
+        if !settings.verify_signature {
+            return Ok(None);
+        }
+        match (&settings.cosign_public_key, &settings.cosign_identity) {
+            (Some(key), None) => {
+                let key = paths::expand_home("cosign_public_key", key)?;
+                paths::check_readable_file("cosign_public_key", &key)?;
+                Ok(Some(Self::Key(key)))
+            }
+            (None, Some(identity)) => Ok(Some(Self::Identity {
+                identity: identity.clone(),
+                issuer: settings
+                    .cosign_oidc_issuer
+                    .clone()
+                    .unwrap_or_else(|| GITHUB_ACTIONS_ISSUER.into()),
+            })),
+            (Some(_), Some(_)) => Err(
+                "both `cosign_public_key` and `cosign_identity` are set; configure only one of \
+                 them"
+                    .into(),
+            ),
+            (None, None) => Err(
+                "`verify_signature` is set, but neither `cosign_public_key` nor \
+                 `cosign_identity` is, so there is nothing to verify the signature against"
+                    .into(),
+            ),
+        }
+    }
+}
+
+/// Returns the checksums asset of `release`, such as `checksums.txt` or
+/// `mcp-grafana_0.2.5_checksums.txt`.
+pub(crate) fn checksums_asset(release: &zed::GithubRelease) -> Option<&zed::GithubReleaseAsset> {
+    release
+        .assets
+        .iter()
+        .find(|asset| asset.name.ends_with("checksums.txt"))
+}
+
+/// Checks `archive`, the contents of the asset `asset_name`, against the
+/// SHA-256 listed for it in the checksums asset `checksums` of `release`.
+///
+/// With a `signature`, the checksums are first verified with `cosign`,
+/// which covers the archive through its checksum. The signature material
+/// is downloaded into `dir`.
+pub(crate) fn verify_checksum(
+    release: &zed::GithubRelease,
+    checksums: &zed::GithubReleaseAsset,
+    asset_name: &str,
+    archive: &[u8],
+    signature: Option<&Signature>,
+    dir: &str,
+) -> Result<()> {
+    let listing = fetch(checksums)?;
+    if let Some(signature) = signature {
+        verify_signature(release, checksums, &listing, signature, dir)?;
+    }
+    let listing = String::from_utf8_lossy(&listing);
+    let expected = find_checksum(&listing, asset_name)
+        .ok_or_else(|| format!("'{}' lists no checksum for '{asset_name}'", checksums.name))?;
+    let actual = to_hex(&sha256(archive));
+    if !expected.eq_ignore_ascii_case(&actual) {
+        return Err(format!(
+            "the checksum of '{asset_name}' doesn't match '{}': expected {expected}, got \
+             {actual}; refusing to install it",
+            checksums.name
+        ));
+    }
+    Ok(())
+}
+
+/// Verifies the cosign signature of `listing`, the contents of the
+/// checksums asset `checksums`, by running `cosign verify-blob`.
+///
+/// Releases signed keylessly publish a `.sigstore.json` bundle, and
+/// releases signed with a key a `.sig` file.
+fn verify_signature(
+    release: &zed::GithubRelease,
+    checksums: &zed::GithubReleaseAsset,
+    listing: &[u8],
+    signature: &Signature,
+    dir: &str,
+) -> Result<()> {
+    let find = |suffix: &str| {
+        let name = format!("{}{suffix}", checksums.name);
+        release.assets.iter().find(|asset| asset.name == name)
+    };
+    let material = find(".sigstore.json")
+        .map(|asset| ("--bundle", asset))
+        .or_else(|| find(".sig").map(|asset| ("--signature", asset)));
+    let Some((flag, material)) = material else {
+        return Err(format!(
+            "release {} publishes no cosign signature of '{}'; refusing to install it, since \
+             `verify_signature` is set",
+            release.version, checksums.name
+        ));
+    };
+
+    let write = |name: &str, contents: &[u8]| {
+        let path = format!("{dir}/{name}");
+        fs::write(&path, contents).map_err(|err| format!("failed to write '{path}': {err}"))?;
+        paths::in_work_dir(&path)
+    };
+    let listing_path = write(&checksums.name, listing)?;
+    let material_path = write(&material.name, &fetch(material)?)?;
+
+    let mut argv = vec![
+        "cosign".into(),
+        "verify-blob".into(),
+        flag.into(),
+        material_path,
+    ];
+    match signature {
+        Signature::Key(key) => argv.extend(["--key".into(), key.clone()]),
+        Signature::Identity { identity, issuer } => argv.extend([
+            "--certificate-identity".into(),
+            identity.clone(),
+            "--certificate-oidc-issuer".into(),
+            issuer.clone(),
+        ]),
+    }
+    argv.push(listing_path);
+
+    let (platform, _) = zed::current_platform();
+    let found = match platform {
+        zed::Os::Windows => true,
+        zed::Os::Mac | zed::Os::Linux => subprocess::run(
+            "cosign lookup",
+            vec!["sh".into(), "-c".into(), "command -v cosign".into()],
+            COSIGN_TIMEOUT_SECS,
+        )
+        .is_ok(),
+    };
+    if !found {
+        return Err("`verify_signature` is set, but `cosign` isn't installed".into());
+    }
+    subprocess::run("cosign", argv, COSIGN_TIMEOUT_SECS).map_err(|err| {
+        format!(
+            "the cosign signature of '{}' in release {} is invalid; refusing to install it: \
+             {err}",
+            checksums.name, release.version
+        )
+    })?;
+    Ok(())
+}
+
+/// Downloads the contents of `asset`.
+fn fetch(asset: &zed::GithubReleaseAsset) -> Result<Vec<u8>> {
+    let response = HttpRequest::builder()
+        .method(HttpMethod::Get)
+        .url(&asset.download_url)
+        .redirect_policy(RedirectPolicy::FollowLimit(5))
+        .build()?
+        .fetch()
+        .map_err(|err| {
+            format!(
+                "failed to download '{}' from {}: {err}",
+                asset.name, asset.download_url
+            )
+        })?;
+    Ok(response.body)
+}
+
+/// Returns the checksum of `asset_name` in a listing in the format of
+/// `sha256sum`, with one `<checksum>  <file name>` line per file.
+fn find_checksum<'a>(listing: &'a str, asset_name: &str) -> Option<&'a str> {
+    listing.lines().find_map(|line| {
+        let (checksum, name) = line.trim().split_once(char::is_whitespace)?;
+        // `sha256sum` marks files hashed in binary mode with `*`.
+        let name = name.trim_start().trim_start_matches('*');
+        (name == asset_name).then_some(checksum)
+    })
+}
+
+/// Returns the SHA-256 digest of `data` in hex.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&sha256(data))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Returns the SHA-256 digest of `data`, as specified in FIPS 180-4.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_matches_nist_vectors() {
+        let cases: [(&[u8], &str); 3] = [
+            (
+                b"",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(sha256_hex(data), expected);
+        }
+    }
+
+    #[test]
+    fn sha256_pads_across_block_boundaries() {
+        let cases = [
+            (
+                55,
+                "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318",
+            ),
+            (
+                56,
+                "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a",
+            ),
+            (
+                64,
+                "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb",
+            ),
+            (
+                1_000_000,
+                "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+            ),
+        ];
+        for (len, expected) in cases {
+            assert_eq!(sha256_hex(&vec![b'a'; len]), expected, "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn finds_checksums_in_sha256sum_listings() {
+        let listing = "0000000000000000000000000000000000000000000000000000000000000000  mcp-grafana_Linux_arm64.tar.gz\n\
+                       0eb3e36bfb24dcd9bb1d1bece1531216b59539a8fde17ee80224af0653c92aa3 *mcp-grafana_Linux_x86_64.tar.gz\n";
+        assert_eq!(
+            find_checksum(listing, "mcp-grafana_Linux_arm64.tar.gz"),
+            Some("0000000000000000000000000000000000000000000000000000000000000000")
+        );
+        assert_eq!(
+            find_checksum(listing, "mcp-grafana_Linux_x86_64.tar.gz"),
+            Some(sha256_hex(b"archive").as_str())
+        );
+        assert_eq!(find_checksum(listing, "mcp-grafana_Linux_x86_64"), None);
+        assert_eq!(find_checksum("", "mcp-grafana_Linux_x86_64.tar.gz"), None);
+    }
+}
@@ -0,0 +1,185 @@
+//! `${VAR}` interpolation of environment variables in setting values.
+
+use std::cell::Cell;
+
+use zed_extension_api::{Result, serde_json::Value};
+
+use crate::environment;
+
+/// Replaces `${VAR}` in every string within `value` with the value of the
+/// variable `VAR`, as returned by `lookup`.
+///
+/// `$$` is replaced with a single `$`, and any other `$` is kept as-is.
+/// Object keys are left alone, and substituted values are not themselves
+/// interpolated. It is an error for a variable to be unset.
+pub(crate) fn interpolate(
+    value: &mut Value,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    interpolate_at(value, &mut String::new(), lookup)
+}
+
+fn interpolate_at(
+    value: &mut Value,
+    path: &mut String,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        Value::String(string) => {
+            *string = interpolate_str(string, lookup)
+                .map_err(|err| format!("failed to interpolate `{path}`: {err}"))?;
+        }
+        Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{index}]"));
+                interpolate_at(value, path, lookup)?;
+                path.truncate(len);
+            }
+        }
+        Value::Object(values) => {
+            for (key, value) in values.iter_mut() {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+                interpolate_at(value, path, lookup)?;
+                path.truncate(len);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+/// Returns whether `input` references a variable as `${VAR}`.
+pub(crate) fn references_var(input: &str) -> bool {
+    let referenced = Cell::new(false);
+    let _ = interpolate_str(input, &|_| {
+        referenced.set(true);
+        Some(String::new())
+    });
+    referenced.get()
+}
+
+/// Interpolates a single string; see [`interpolate`].
+fn interpolate_str(
+    input: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let Some(end) = after.find('}') else {
+                return Err("unterminated `${`; write `$$` for a literal `$`".into());
+            };
+            let name = &after[..end];
+            if !environment::is_valid_name(name) {
+                return Err(format!("invalid variable name '{name}' in `${{{name}}}`"));
+            }
+            let value = lookup(name).ok_or_else(|| format!("variable {name} is not set"))?;
+            output.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use zed_extension_api::serde_json::json;
+
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/me".into()),
+            "TOKEN" => Some("glsa_${HOME}$$".into()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn interpolates_strings() {
+        let cases = [
+            ("${HOME}/token", "/home/me/token"),
+            ("${HOME}${HOME}", "/home/me/home/me"),
+            ("a${EMPTY}b", "ab"),
+            ("$$HOME", "$HOME"),
+            ("$${HOME}", "${HOME}"),
+            ("$$$${HOME}", "$${HOME}"),
+            ("$$${HOME}", "$/home/me"),
+            ("cost: $5", "cost: $5"),
+            ("trailing $", "trailing $"),
+            ("$HOME", "$HOME"),
+            ("${TOKEN}", "glsa_${HOME}$$"),
+            ("no variables", "no variables"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                interpolate_str(input, &lookup).as_deref(),
+                Ok(expected),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_missing_and_malformed_variables() {
+        let cases = [
+            ("${UNSET}", "variable UNSET is not set"),
+            ("${HOME", "unterminated `${`; write `$$` for a literal `$`"),
+            ("${}", "invalid variable name '' in `${}`"),
+            ("${1ST}", "invalid variable name '1ST' in `${1ST}`"),
+            ("${A-B}", "invalid variable name 'A-B' in `${A-B}`"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                interpolate_str(input, &lookup).unwrap_err(),
+                expected,
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn interpolates_nested_values_but_not_keys() {
+        let mut value = json!({
+            "api_key_file": "${HOME}/token",
+            "custom_headers": { "${HOME}": "${HOME}" },
+            "extra_args": ["--a", "${HOME}", 3, true, null],
+            "profiles": { "dev": { "nested": ["${HOME}"] } },
+        });
+        interpolate(&mut value, &lookup).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "api_key_file": "/home/me/token",
+                "custom_headers": { "${HOME}": "/home/me" },
+                "extra_args": ["--a", "/home/me", 3, true, null],
+                "profiles": { "dev": { "nested": ["/home/me"] } },
+            })
+        );
+    }
+
+    #[test]
+    fn errors_name_the_setting() {
+        let mut value = json!({ "profiles": { "dev": { "extra_args": ["ok", "${UNSET}"] } } });
+        assert_eq!(
+            interpolate(&mut value, &lookup).unwrap_err(),
+            "failed to interpolate `profiles.dev.extra_args[1]`: variable UNSET is not set"
+        );
+    }
+}
@@ -1,136 +1,197 @@
-use std::{env, fs};
+mod binary;
+mod credentials;
+mod dev;
+mod docker;
+mod dotenv;
+mod environment;
+mod grafana_url;
+mod headers;
+mod integrity;
+mod interpolate;
+mod limits;
+mod lock;
+mod log_file;
+mod manifest;
+mod netrc;
+mod paths;
+mod precedence;
+mod preflight;
+mod proxy;
+mod release_cache;
+mod releases;
+mod remote;
+mod retry;
+mod schema;
+mod server;
+mod settings;
+mod status;
+mod subprocess;
+mod suggest;
+mod tool_names;
+mod tools;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use serde::Deserialize;
 use zed_extension_api::{
-    self as zed, Command, ContextServerId, Project, Result, serde_json,
-    settings::ContextServerSettings,
+    self as zed, Command, ContextServerConfiguration, ContextServerId, Project, Result,
 };
 
-const REPO_NAME: &str = "grafana/mcp-grafana";
-const BINARY_NAME: &str = "mcp-grafana";
-
-#[derive(Debug, Deserialize)]
-struct GrafanaContextServerSettings {
-    /// The URL of the Grafana instance.
-    ///
-    /// Note this is marked as optional because it may come from the
-    /// `GRAFANA_URL` environment variable instead.
-    #[serde(default)]
-    grafana_url: Option<String>,
-
-    /// The API key of the Grafana instance.
-    ///
-    /// This is optional if the Grafana instance is accessible without
-    /// authentication. It can also be set using the `GRAFANA_API_KEY`
-    /// environment variable.
-    #[serde(default)]
-    grafana_api_key: Option<String>,
-
-    /// Enabled categories of tools.
-    ///
-    /// See the [README of the Grafana MCP server][readme] to see the list
-    /// of available categories.
-    ///
-    /// Defaults to `None`, which means all tools are enabled.
-    ///
-    /// [readme]: https://github.com/grafana/mcp-grafana
-    #[serde(default)]
-    enabled_tools: Option<Vec<String>>,
-
-    /// Enable the Grafana MCP server's debug flag.
-    ///
-    /// This will cause requests to and responses from the Grafana
-    /// instance to be logged by the MCP server.
-    ///
-    /// Defaults to false.
-    #[serde(default)]
-    debug: bool,
-}
+use crate::{
+    binary::BinaryCache,
+    credentials::{Credentials, TokenCache},
+    docker::Runtime,
+    environment::{Environment, LoginShell},
+    server::Server,
+    settings::{EnvOverride, LogLevel},
+};
 
-struct GrafanaModelContextExtension {
-    cached_binary_path: Option<String>,
-}
+/// The largest allowed `timeout_seconds`.
+const MAX_TIMEOUT_SECONDS: u64 = 3600;
 
-impl GrafanaModelContextExtension {
-    fn context_server_binary_path(
-        &mut self,
-        _context_server_id: &ContextServerId,
-    ) -> Result<String> {
-        if let Some(path) = &self.cached_binary_path {
-            if fs::metadata(path).is_ok_and(|stat| stat.is_file()) {
-                return Ok(path.clone());
-            }
-        }
+/// The environment variables other than credentials which the extension
+/// resolves itself, rather than passing them through.
+const RESOLVED_ENV_VARS: &[&str] = &["GRAFANA_URL", "GRAFANA_ORG_ID", headers::ENV_VAR];
 
-        let release = zed::latest_github_release(
-            REPO_NAME,
-            zed::GithubReleaseOptions {
-                require_assets: true,
-                pre_release: false,
-            },
-        )?;
+/// Whether [`debug_log`] writes to Zed's log, which it does while the
+/// context server being started has `"log_level": "debug"`, or the
+/// deprecated `"debug": true`.
+static DEBUG: AtomicBool = AtomicBool::new(false);
 
-        let (platform, arch) = zed::current_platform();
-        let asset_name = format!(
-            "{BINARY_NAME}_{os}_{arch}.{ext}",
-            arch = match arch {
-                zed::Architecture::Aarch64 => "arm64",
-                zed::Architecture::X86 => "i386",
-                zed::Architecture::X8664 => "x86_64",
-            },
-            os = match platform {
-                zed::Os::Mac => "Darwin",
-                zed::Os::Linux => "Linux",
-                zed::Os::Windows => "Windows",
-            },
-            ext = match platform {
-                zed::Os::Mac | zed::Os::Linux => "tar.gz",
-                zed::Os::Windows => "zip",
-            }
-        );
+/// Writes a message to Zed's log.
+fn log(message: impl fmt::Display) {
+    eprintln!("mcp-server-grafana: {message}");
+}
 
-        let asset = release
-            .assets
-            .iter()
-            .find(|asset| asset.name == asset_name)
-            .ok_or_else(|| format!("no asset found matching {:?}", asset_name))?;
-
-        let version_dir = format!("{BINARY_NAME}-{}", release.version);
-        fs::create_dir_all(&version_dir)
-            .map_err(|err| format!("failed to create directory '{version_dir}': {err}"))?;
-        let binary_path = format!("{version_dir}/{BINARY_NAME}");
-
-        if !fs::metadata(&binary_path).is_ok_and(|stat| stat.is_file()) {
-            let file_kind = match platform {
-                zed::Os::Mac | zed::Os::Linux => zed::DownloadedFileType::GzipTar,
-                zed::Os::Windows => zed::DownloadedFileType::Zip,
-            };
+/// Writes a message logged on every start, such as which settings were
+/// resolved, to Zed's log if [`DEBUG`] is set.
+fn debug_log(message: impl fmt::Display) {
+    if DEBUG.load(Ordering::Relaxed) {
+        log(message);
+    }
+}
 
-            zed::download_file(&asset.download_url, &version_dir, file_kind)
-                .map_err(|e| format!("failed to download file: {e}"))?;
+/// Formats `env` for logging, masking `secrets`.
+fn redacted_env(env: &[(String, String)], secrets: &[String]) -> String {
+    env.iter()
+        .map(|(key, value)| {
+            if secrets.contains(value) {
+                format!("{key}=***")
+            } else {
+                format!("{key}={value}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-            zed::make_file_executable(&binary_path)?;
+/// Returns whether the variable `name` probably holds a secret, so that
+/// its value should be masked in logs.
+fn looks_secret(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    ["TOKEN", "PASSWORD", "SECRET", "KEY"]
+        .iter()
+        .any(|word| name.contains(word))
+}
 
-            // Removes old versions
-            let entries =
-                fs::read_dir(".").map_err(|e| format!("failed to list working directory {e}"))?;
-            for entry in entries {
-                let entry = entry.map_err(|e| format!("failed to load directory entry {e}"))?;
-                if entry.file_name().to_str() != Some(&version_dir) {
-                    fs::remove_dir_all(entry.path()).ok();
+/// Applies `overrides` to `env`, returning the final environment sorted by
+/// name.
+///
+/// It is an error for two variables to end up with the same name but
+/// different values.
+fn apply_env_overrides(
+    env: Vec<(String, String)>,
+    overrides: &BTreeMap<String, EnvOverride>,
+) -> Result<Vec<(String, String)>> {
+    let mut renamed = BTreeMap::new();
+    for (key, value) in env {
+        let names = match overrides.get(&key) {
+            Some(names) => names.names().to_vec(),
+            None => vec![key.clone()],
+        };
+        for name in names {
+            match renamed.get(&name) {
+                Some(existing) if existing != &value => {
+                    return Err(format!(
+                        "`env_overrides` exports '{key}' as '{name}', which is already set to a \
+                         different value"
+                    ));
+                }
+                _ => {
+                    renamed.insert(name, value.clone());
                 }
             }
         }
+    }
+    Ok(renamed.into_iter().collect())
+}
 
-        self.cached_binary_path = Some(binary_path.clone());
-        Ok(binary_path)
+/// The server flags which the extension sets from its own settings, and
+/// which therefore can't be given in `extra_args`.
+const MANAGED_FLAGS: &[&str] = &[
+    "t",
+    "transport",
+    "enabled-tools",
+    "enabled-tool-names",
+    "disabled-tool-names",
+    "disable-write",
+    "debug",
+    "log-level",
+    "timeout",
+    "tls-cert-file",
+    "tls-key-file",
+    "tls-ca-file",
+    "tls-skip-verify",
+    "datasource-allowlist",
+    "default-prometheus-datasource",
+    "default-loki-datasource",
+    "max-log-lines",
+    "max-series",
+    "max-query-range",
+];
+
+/// Returns an error if any of `extra_args` is a flag managed by the
+/// extension, such as `--enabled-tools` or `--disable-<category>`.
+fn check_extra_args(extra_args: &[String]) -> Result<()> {
+    for arg in extra_args {
+        let Some(flag) = arg.strip_prefix('-') else {
+            continue;
+        };
+        let name = flag.trim_start_matches('-');
+        let name = name.split_once('=').map_or(name, |(name, _)| name);
+        let managed = MANAGED_FLAGS.contains(&name)
+            || name
+                .strip_prefix("disable-")
+                .is_some_and(|category| tools::CATEGORIES.contains(&category));
+        if managed {
+            return Err(format!(
+                "`extra_args` contains '{arg}', which is managed by the extension; use the \
+                 corresponding setting instead"
+            ));
+        }
     }
+    Ok(())
+}
+
+struct GrafanaModelContextExtension {
+    binary_cache: BinaryCache,
+    token_cache: TokenCache,
+    login_shell: LoginShell,
+    /// The `--help` output of each container image, which takes long to
+    /// read since it runs the image.
+    image_help: HashMap<String, String>,
 }
 
 impl zed::Extension for GrafanaModelContextExtension {
     fn new() -> Self {
         Self {
-            cached_binary_path: None,
+            binary_cache: BinaryCache::default(),
+            token_cache: TokenCache::default(),
+            login_shell: LoginShell::default(),
+            image_help: HashMap::new(),
         }
     }
 
@@ -139,43 +200,426 @@ impl zed::Extension for GrafanaModelContextExtension {
         context_server_id: &ContextServerId,
         project: &Project,
     ) -> Result<Command> {
-        let settings = ContextServerSettings::for_project("mcp-server-grafana", project)?;
-        let Some(settings) = settings.settings else {
-            return Err("missing Grafana settings".into());
+        let settings = settings::parse(settings::load(context_server_id, project)?)?;
+        DEBUG.store(
+            settings.log_level == Some(LogLevel::Debug),
+            Ordering::Relaxed,
+        );
+        if let Some(profile) = &settings.profile {
+            debug_log(format_args!("using {profile}"));
+        }
+        log_file::check_supported(settings.log_file.as_deref())?;
+
+        let process_env = Environment::load(
+            settings.env_file.as_deref(),
+            settings.inherit_shell_env.then_some(&mut self.login_shell),
+        )?;
+        if settings.strict_secrets
+            || process_env
+                .var("ZED_MCP_GRAFANA_STRICT_SECRETS")
+                .is_some_and(|value| value == "1")
+        {
+            credentials::check_no_plaintext_secrets(&settings)?;
+        }
+        if let Some(remote_url) = &settings.remote_url {
+            return remote::command(remote_url, &settings, &process_env);
+        }
+
+        let grafana_url = grafana_url::resolve(&settings, process_env.var("GRAFANA_URL"))?;
+        let grafana_url = grafana_url::normalize(&grafana_url)?;
+        let (grafana_url, url_credentials) = grafana_url::strip_credentials(&grafana_url)?;
+        let credentials = credentials::resolve(
+            &settings,
+            &process_env,
+            &grafana_url,
+            url_credentials,
+            &mut self.token_cache,
+        )?;
+        if !settings.allow_insecure
+            && !matches!(credentials, Credentials::Anonymous)
+            && let Some(host) = grafana_url::insecure_host(&grafana_url)
+        {
+            return Err(format!(
+                "refusing to send Grafana credentials to '{host}' over plain http://; use an \
+                 https:// URL, or set `allow_insecure` to true if this is intended"
+            ));
+        }
+
+        headers::validate("custom_headers", &settings.custom_headers)?;
+        if settings.validate_credentials {
+            preflight::check(
+                &grafana_url,
+                &credentials,
+                &headers::pairs(&settings.custom_headers),
+            )?;
+        }
+
+        let org_id = settings.settings_precedence.pick(
+            settings.org_id.map(|org_id| org_id.to_string()),
+            process_env.var("GRAFANA_ORG_ID"),
+        );
+        if let Some(org_id) = &org_id
+            && !org_id.parse::<u64>().is_ok_and(|org_id| org_id > 0)
+        {
+            return Err(format!(
+                "invalid Grafana organization id '{org_id}'; expected a positive integer"
+            ));
+        }
+
+        let mut env = vec![("GRAFANA_URL".into(), grafana_url.clone())];
+        env.extend(org_id.map(|org_id| ("GRAFANA_ORG_ID".into(), org_id)));
+        env.extend(credentials.into_env());
+        if !settings.custom_headers.is_empty() {
+            env.push((
+                headers::ENV_VAR.into(),
+                headers::env_value(&settings.custom_headers),
+            ));
+        }
+        // Variables which the extension resolves itself have already been
+        // accounted for above, so only pass through the rest.
+        for (key, value) in process_env.loaded_vars() {
+            if !RESOLVED_ENV_VARS.contains(&key) && !credentials::ENV_VARS.contains(&key) {
+                env.push((key.into(), value.into()));
+            }
+        }
+
+        env.extend(proxy::env(&settings, &process_env));
+
+        // The program to run, which is `docker` when running `image`.
+        let (program, image) = match settings.runtime {
+            Runtime::Binary => {
+                let options = self.binary_cache.options(&settings, &process_env)?;
+                (
+                    self.binary_cache
+                        .get(context_server_id.as_ref(), &options)?,
+                    None,
+                )
+            }
+            Runtime::Docker => (
+                docker::find()?,
+                Some(docker::image(&settings, || {
+                    binary::latest_tag(&self.binary_cache.options(&settings, &process_env)?)
+                })?),
+            ),
         };
-        let settings: GrafanaContextServerSettings =
-            serde_json::from_value(settings).map_err(|e| e.to_string())?;
-
-        let Some(grafana_url) = env::var("GRAFANA_URL").ok().or(settings.grafana_url) else {
-            return Err(
-                "missing Grafana URL; configure in `grafana_url` setting or GRAFANA_URL env var"
-                    .into(),
-            );
+        let mut server = match &image {
+            Some(image) => Server::docker(&program, image, self.image_help.get(image).cloned()),
+            None => Server::new(&program),
         };
-        let api_key = env::var("GRAFANA_API_KEY")
-            .ok()
-            .or(settings.grafana_api_key);
-
-        let mut env = vec![("GRAFANA_URL".into(), grafana_url)];
-        if let Some(api_key) = api_key {
-            env.push(("GRAFANA_API_KEY".into(), api_key));
+        if let Some(image) = &image
+            && !self.image_help.contains_key(image)
+        {
+            let help = server.help()?.to_string();
+            self.image_help.insert(image.clone(), help);
+        }
+        // The transport is passed even though stdio is the binary's default,
+        // since the image serves SSE by default and releases may change it.
+        let mut args = vec!["-t".into(), settings.transport.as_str().into()];
+        args.extend(tools::args(
+            settings.enabled_tools.as_deref(),
+            settings.disabled_tools.as_deref(),
+            settings.tools.as_ref(),
+            settings.allow_unknown_tools,
+        )?);
+        args.extend(tool_names::args(
+            &settings.enabled_tool_names,
+            &settings.disabled_tool_names,
+            |category| {
+                tools::is_enabled(
+                    category,
+                    settings.enabled_tools.as_deref(),
+                    settings.disabled_tools.as_deref(),
+                    settings.tools.as_ref(),
+                )
+            },
+            &mut server,
+        )?);
+        if settings.read_only {
+            if !server.supports_flag("disable-write")? {
+                return Err(format!(
+                    "`read_only` is set, but {} has no `--disable-write` flag to enforce it; \
+                     refusing to start with write tools enabled",
+                    server.description()
+                ));
+            }
+            args.push("--disable-write".into());
+        }
+        if let Some(timeout) = settings.timeout_seconds {
+            if timeout == 0 || timeout > MAX_TIMEOUT_SECONDS {
+                return Err(format!(
+                    "invalid `timeout_seconds` {timeout}; expected between 1 and \
+                     {MAX_TIMEOUT_SECONDS}"
+                ));
+            }
+            server.require_flag("timeout_seconds", "timeout")?;
+            args.extend(["--timeout".into(), format!("{timeout}s")]);
+        }
+        let datasources: Vec<&str> = settings
+            .datasource_allowlist
+            .iter()
+            .map(|uid| uid.trim())
+            .collect();
+        if !datasources.is_empty() {
+            if datasources.iter().any(|uid| uid.is_empty()) {
+                return Err("`datasource_allowlist` must not contain empty UIDs".into());
+            }
+            if !server.supports_flag("datasource-allowlist")? {
+                return Err(format!(
+                    "`datasource_allowlist` is set, but {} has no `--datasource-allowlist` flag \
+                     to enforce it; refusing to start with access to all datasources",
+                    server.description()
+                ));
+            }
+            args.extend(["--datasource-allowlist".into(), datasources.join(",")]);
+        }
+        for (setting, flag, uid) in [
+            (
+                "default_prometheus_datasource",
+                "default-prometheus-datasource",
+                &settings.default_prometheus_datasource,
+            ),
+            (
+                "default_loki_datasource",
+                "default-loki-datasource",
+                &settings.default_loki_datasource,
+            ),
+        ] {
+            let Some(uid) = uid else {
+                continue;
+            };
+            if uid.trim().is_empty() {
+                return Err(format!("`{setting}` must not be empty"));
+            }
+            server.require_flag(setting, flag)?;
+            args.extend([format!("--{flag}"), uid.trim().into()]);
+        }
+        args.extend(limits::args(&settings, &mut server)?);
+        if let Some(level) = settings.log_level {
+            server.require_flag("log_level", "log-level")?;
+            args.extend(["--log-level".into(), level.as_str().into()]);
+            if level == LogLevel::Debug {
+                args.push("--debug".into());
+            }
+        }
+        match (settings.tls_cert_file, settings.tls_key_file) {
+            (Some(cert_file), Some(key_file)) => {
+                paths::check_readable_file("tls_cert_file", &cert_file)?;
+                paths::check_readable_file("tls_key_file", &key_file)?;
+                args.extend(["--tls-cert-file".into(), cert_file]);
+                args.extend(["--tls-key-file".into(), key_file]);
+            }
+            (Some(_), None) => {
+                return Err("`tls_cert_file` is set but `tls_key_file` is missing".into());
+            }
+            (None, Some(_)) => {
+                return Err("`tls_key_file` is set but `tls_cert_file` is missing".into());
+            }
+            (None, None) => {}
+        }
+        if settings.tls_skip_verify {
+            if settings.tls_ca_file.is_some() {
+                return Err(
+                    "both `tls_ca_file` and `tls_skip_verify` are set, but skipping verification \
+                     makes the CA bundle pointless; remove `tls_skip_verify` to verify against \
+                     the CA bundle, or remove `tls_ca_file` to skip verification"
+                        .into(),
+                );
+            }
+            if !settings.tls_skip_verify_any_host && !grafana_url::is_local(&grafana_url) {
+                return Err(format!(
+                    "refusing to skip TLS verification for non-local Grafana URL '{grafana_url}'; \
+                     set `tls_skip_verify_any_host` as well if this is intended"
+                ));
+            }
+            args.push("--tls-skip-verify".into());
+        }
+        if let Some(ca_file) = settings.tls_ca_file {
+            paths::check_readable_file("tls_ca_file", &ca_file)?;
+            args.extend(["--tls-ca-file".into(), ca_file]);
         }
 
-        let mut args = vec![];
-        if let Some(enabled_tools) = settings.enabled_tools {
-            args.push("--enabled-tools".into());
-            args.push(enabled_tools.join(","));
+        check_extra_args(&settings.extra_args)?;
+        args.extend(settings.extra_args);
+
+        for (key, value) in settings.extra_env {
+            if !environment::is_valid_name(&key) {
+                return Err(format!(
+                    "invalid variable name '{key}' in `extra_env`; names must consist of \
+                     letters, digits and underscores, and not start with a digit"
+                ));
+            }
+            if env.iter().any(|(existing, _)| existing == &key) {
+                return Err(format!(
+                    "`extra_env` sets '{key}', which the extension already sets; use the \
+                     corresponding setting instead"
+                ));
+            }
+            env.push((key, value));
         }
-        if settings.debug {
-            args.push("--debug".into());
+
+        let secrets: Vec<String> = env
+            .iter()
+            .filter(|(key, value)| {
+                credentials::SECRET_ENV_VARS.contains(&key.as_str())
+                    || key == headers::ENV_VAR
+                    || looks_secret(key)
+                    || (key.ends_with("_proxy") || key.ends_with("_PROXY"))
+                        && proxy::has_credentials(value)
+            })
+            .map(|(_, value)| value.clone())
+            .collect();
+        let env = apply_env_overrides(env, &settings.env_overrides)?;
+
+        let mut command = match &image {
+            Some(image) => docker::command(program, image, &settings.docker_args, args, env),
+            None => Command {
+                command: program,
+                args,
+                env,
+            },
+        };
+        if let Some(path) = &settings.log_file {
+            command = log_file::redirect(command, path)?;
         }
+        debug_log(format_args!(
+            "starting {} {} with environment {}",
+            command.command,
+            command.args.join(" "),
+            redacted_env(&command.env, &secrets),
+        ));
+        Ok(command)
+    }
 
-        Ok(Command {
-            command: self.context_server_binary_path(context_server_id)?,
-            args,
-            env,
-        })
+    fn context_server_configuration(
+        &mut self,
+        _context_server_id: &ContextServerId,
+        _project: &Project,
+    ) -> Result<Option<ContextServerConfiguration>> {
+        Ok(Some(ContextServerConfiguration {
+            installation_instructions: include_str!(
+                "../configuration/installation_instructions.md"
+            )
+            .into(),
+            default_settings: include_str!("../configuration/default_settings.jsonc").into(),
+            settings_schema: schema::settings_schema().to_string(),
+        }))
     }
 }
 
 zed::register_extension!(GrafanaModelContextExtension);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_env_masks_secret_values() {
+        let env = vec![
+            ("GRAFANA_URL".into(), "https://grafana.example.com".into()),
+            ("GRAFANA_SERVICE_ACCOUNT_TOKEN".into(), "glsa_token".into()),
+        ];
+        assert_eq!(
+            redacted_env(&env, &["glsa_token".into()]),
+            "GRAFANA_URL=https://grafana.example.com GRAFANA_SERVICE_ACCOUNT_TOKEN=***"
+        );
+    }
+
+    #[test]
+    fn secret_looking_names() {
+        for name in ["MY_API_KEY", "vault_token", "DB_PASSWORD", "client_secret"] {
+            assert!(looks_secret(name), "{name}");
+        }
+        for name in ["GRAFANA_URL", "GRAFANA_ORG_ID", "HOME"] {
+            assert!(!looks_secret(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn env_overrides_rename_and_duplicate_variables() {
+        let overrides: BTreeMap<String, EnvOverride> =
+            zed::serde_json::from_value(zed::serde_json::json!({
+                "GRAFANA_SERVICE_ACCOUNT_TOKEN": "X_INTERNAL_GRAFANA_TOKEN",
+                "GRAFANA_URL": ["GRAFANA_URL", "X_INTERNAL_GRAFANA_URL"],
+            }))
+            .unwrap();
+        let env = vec![
+            ("GRAFANA_URL".into(), "https://grafana.example.com".into()),
+            ("GRAFANA_SERVICE_ACCOUNT_TOKEN".into(), "glsa_token".into()),
+            ("A_PROXY_VAR".into(), "1".into()),
+        ];
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            apply_env_overrides(env, &overrides),
+            Ok(pairs(&[
+                ("A_PROXY_VAR", "1"),
+                ("GRAFANA_URL", "https://grafana.example.com"),
+                ("X_INTERNAL_GRAFANA_TOKEN", "glsa_token"),
+                ("X_INTERNAL_GRAFANA_URL", "https://grafana.example.com"),
+            ]))
+        );
+
+        let clash = pairs(&[
+            ("GRAFANA_URL", "https://a.example.com"),
+            ("URL", "https://b.example.com"),
+        ]);
+        let err = apply_env_overrides(clash, &overrides_of("URL", "GRAFANA_URL")).unwrap_err();
+        assert_eq!(
+            err,
+            "`env_overrides` exports 'URL' as 'GRAFANA_URL', which is already set to a different \
+             value"
+        );
+    }
+
+    fn overrides_of(from: &str, to: &str) -> BTreeMap<String, EnvOverride> {
+        BTreeMap::from([(from.to_string(), EnvOverride::Rename(to.into()))])
+    }
+
+    /// Returns the credentials the server is passed for the settings
+    /// `value` and the environment `vars`, once `env_overrides` apply.
+    fn exported_credentials(
+        value: zed::serde_json::Value,
+        vars: &[(&str, &str)],
+    ) -> Vec<(String, String)> {
+        let settings = settings::parse(value).unwrap();
+        let credentials = credentials::resolve(
+            &settings,
+            &Environment::with_vars(vars),
+            "https://grafana.example.com",
+            None,
+            &mut TokenCache::default(),
+        )
+        .unwrap();
+        apply_env_overrides(credentials.into_env(), &settings.env_overrides).unwrap()
+    }
+
+    #[test]
+    fn env_overrides_apply_after_precedence() {
+        let vars = [("GRAFANA_SERVICE_ACCOUNT_TOKEN", "glsa_env")];
+        let renamed =
+            |token: &str| vec![("X_INTERNAL_GRAFANA_TOKEN".to_string(), token.to_string())];
+        let mut value = zed::serde_json::json!({
+            "grafana_service_account_token": "glsa_settings",
+            "env_overrides": { "GRAFANA_SERVICE_ACCOUNT_TOKEN": "X_INTERNAL_GRAFANA_TOKEN" },
+        });
+        assert_eq!(
+            exported_credentials(value.clone(), &vars),
+            renamed("glsa_settings")
+        );
+        value["settings_precedence"] = "env".into();
+        assert_eq!(
+            exported_credentials(value.clone(), &vars),
+            renamed("glsa_env")
+        );
+        value
+            .as_object_mut()
+            .unwrap()
+            .remove("grafana_service_account_token");
+        value["settings_precedence"] = "settings".into();
+        assert_eq!(exported_credentials(value, &vars), renamed("glsa_env"));
+    }
+}
@@ -0,0 +1,67 @@
+//! Limits on the queries the server makes on behalf of the agent.
+
+use zed_extension_api::Result;
+
+use crate::{server::Server, settings::GrafanaContextServerSettings};
+
+/// The largest allowed `max_log_lines` and `max_series`.
+const MAX_COUNT: u64 = 100_000;
+
+/// Returns the server arguments for the query limits in `settings`.
+///
+/// It is an error for a limit to be out of range, or for the server not to
+/// support a limit which is set.
+pub(crate) fn args(
+    settings: &GrafanaContextServerSettings,
+    server: &mut Server,
+) -> Result<Vec<String>> {
+    let mut args = vec![];
+    for (setting, flag, value) in [
+        ("max_log_lines", "max-log-lines", settings.max_log_lines),
+        ("max_series", "max-series", settings.max_series),
+    ] {
+        let Some(value) = value else {
+            continue;
+        };
+        if value == 0 || value > MAX_COUNT {
+            return Err(format!(
+                "invalid `{setting}` {value}; expected between 1 and {MAX_COUNT}"
+            ));
+        }
+        server.require_flag(setting, flag)?;
+        args.extend([format!("--{flag}"), value.to_string()]);
+    }
+    if let Some(range) = &settings.max_query_range {
+        if !is_duration(range) {
+            return Err(format!(
+                "invalid `max_query_range` '{range}'; expected a duration such as '6h' or '1h30m'"
+            ));
+        }
+        server.require_flag("max_query_range", "max-query-range")?;
+        args.extend(["--max-query-range".into(), range.clone()]);
+    }
+    Ok(args)
+}
+
+/// Returns whether `value` is a positive duration in the format of Go's
+/// `time.ParseDuration`, limited to hours, minutes and seconds.
+fn is_duration(value: &str) -> bool {
+    let mut rest = value;
+    let mut positive = false;
+    while !rest.is_empty() {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return false;
+        }
+        let (number, unit) = rest.split_at(digits);
+        let Some(after) = ["h", "m", "s"]
+            .iter()
+            .find_map(|suffix| unit.strip_prefix(suffix))
+        else {
+            return false;
+        };
+        positive |= number.bytes().any(|digit| digit != b'0');
+        rest = after;
+    }
+    positive
+}
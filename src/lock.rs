@@ -0,0 +1,194 @@
+//! An advisory lock on installing releases, so that Zed windows which
+//! start the context server at the same time don't download into, or clean
+//! up, the same version directories at once.
+
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use zed_extension_api::Result;
+
+use crate::log;
+
+/// The lock file, in the extension's working directory.
+const FILE_NAME: &str = "install.lock";
+
+/// How often a held lock is checked while waiting for it.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long to wait for another window to finish installing.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How old a lock is when it is taken to be left behind by a Zed which
+/// exited while installing. Longer than any download or build takes.
+const STALE_AFTER: Duration = Duration::from_secs(900);
+
+/// The held lock, which is released when dropped.
+pub(crate) struct InstallLock {
+    path: String,
+    /// Written to the lock file, so that a lock broken as stale and taken
+    /// by another window isn't released by this one.
+    token: String,
+}
+
+impl InstallLock {
+    /// Takes the lock, waiting up to [`WAIT_TIMEOUT`] for another window
+    /// holding it to finish, and breaking it if it is stale.
+    ///
+    /// Whatever the other window installed should be checked for again
+    /// once this returns.
+    pub(crate) fn acquire() -> Result<Self> {
+        Self::acquire_at(FILE_NAME.into())
+    }
+
+    /// Takes the lock at `path`, which is [`FILE_NAME`] outside of tests.
+    fn acquire_at(path: String) -> Result<Self> {
+        // `std::process::id` isn't available to extensions, so use the time.
+        let token = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_nanos())
+            .to_string();
+        let started = SystemTime::now();
+        let mut waiting = false;
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    if let Err(err) = file.write_all(token.as_bytes()) {
+                        fs::remove_file(&path).ok();
+                        return Err(format!("failed to write '{path}': {err}"));
+                    }
+                    return Ok(Self { path, token });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(format!("failed to create '{path}': {err}")),
+            }
+            if age(&path).is_some_and(|age| age >= STALE_AFTER) {
+                break_stale(&path, &token);
+                continue;
+            }
+            if started.elapsed().unwrap_or_default() >= WAIT_TIMEOUT {
+                return Err(format!(
+                    "timed out after {}s waiting for another Zed window to finish installing the \
+                     server; if none is, remove '{path}' from the extension's directory",
+                    WAIT_TIMEOUT.as_secs()
+                ));
+            }
+            if !waiting {
+                log("waiting for another Zed window to finish installing the server");
+                waiting = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for InstallLock {
+    fn drop(&mut self) {
+        if fs::read_to_string(&self.path).is_ok_and(|holder| holder == self.token) {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+/// Returns how long ago the lock at `path` was taken, if it is held.
+fn age(path: &str) -> Option<Duration> {
+    fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()
+}
+
+/// Removes the lock at `path` left behind by a Zed which exited while
+/// installing.
+///
+/// Another window waiting on the same lock may have broken it and taken
+/// it since it was found to be stale, so it is moved aside under a name
+/// unique to `token` first, and only removed if what was moved is still
+/// stale. A lock taken in the meantime is put back, unless yet another
+/// window has taken the lock since.
+fn break_stale(path: &str, token: &str) {
+    let aside = format!("{path}.{token}");
+    if fs::rename(path, &aside).is_err() {
+        // Already broken by another window.
+        return;
+    }
+    match age(&aside) {
+        Some(age) if age >= STALE_AFTER => log(format!(
+            "breaking '{path}', which was taken {}s ago by a Zed that likely exited while \
+             installing",
+            age.as_secs()
+        )),
+        // `hard_link` doesn't replace a lock taken since, unlike `rename`.
+        _ => {
+            if fs::hard_link(&aside, path).is_err() {
+                log(format!(
+                    "'{path}' was taken by two Zed windows at once; restart Zed if installing \
+                     the server fails"
+                ));
+            }
+        }
+    }
+    fs::remove_file(&aside).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// Returns the lock file in a new empty directory named after `test`.
+    fn lock_path(test: &str) -> String {
+        let dir = env::temp_dir().join(format!("zed-mcp-grafana-{test}"));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(FILE_NAME).to_string_lossy().into_owned()
+    }
+
+    /// Returns the names of the files in the lock's directory.
+    fn files_beside(path: &str) -> Vec<String> {
+        let dir = std::path::Path::new(path).parent().unwrap();
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn stale_locks_are_broken_and_taken() {
+        let path = lock_path("stale-lock");
+        fs::write(&path, "left-behind").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - STALE_AFTER - Duration::from_secs(60))
+            .unwrap();
+
+        let lock = InstallLock::acquire_at(path.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), lock.token);
+        assert_eq!(files_beside(&path), [FILE_NAME]);
+        drop(lock);
+        assert!(files_beside(&path).is_empty());
+    }
+
+    #[test]
+    fn fresh_locks_taken_while_breaking_are_put_back() {
+        let path = lock_path("fresh-lock");
+        fs::write(&path, "held").unwrap();
+        break_stale(&path, "late-waiter");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "held");
+        assert_eq!(files_beside(&path), [FILE_NAME]);
+
+        // A lock broken by another window is left alone.
+        fs::remove_file(&path).unwrap();
+        break_stale(&path, "late-waiter");
+        assert!(files_beside(&path).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Redirecting the server's logs to a file.
+
+use std::{fs, path::PathBuf};
+
+use zed_extension_api::{self as zed, Command, Result};
+
+use crate::paths;
+
+/// The script which runs `"$@"` with its stderr copied to the file `$1` as
+/// well as the script's own stderr.
+///
+/// `sh` has no process substitution, so stderr goes through a named pipe
+/// to `tee`. The pipe is removed once both ends are open.
+const TEE_SCRIPT: &str = r#"log=$1; shift
+fifo="${TMPDIR:-/tmp}/mcp-grafana-stderr.$$"
+rm -f "$fifo" && mkfifo "$fifo" || exit 1
+tee -a "$log" <"$fifo" >&2 &
+exec 2>"$fifo"
+rm -f "$fifo"
+exec "$@""#;
+
+/// Checks that `log_file` can be used on this platform, before anything
+/// is installed.
+pub(crate) fn check_supported(log_file: Option<&str>) -> Result<()> {
+    let (platform, _) = zed::current_platform();
+    if log_file.is_some() && platform == zed::Os::Windows {
+        return Err(
+            "`log_file` is not supported on Windows, where the server's logs only go to Zed's \
+             log; remove it"
+                .into(),
+        );
+    }
+    Ok(())
+}
+
+/// Wraps `command` so that its stderr, where the server logs, is written
+/// to the file at `path` as well as Zed's log.
+///
+/// A leading `~/` is expanded to the home directory, and missing parent
+/// directories are created. The file is truncated every time the server
+/// starts, so it only ever holds the logs of the current run.
+pub(crate) fn redirect(command: Command, path: &str) -> Result<Command> {
+    let path = PathBuf::from(paths::expand_home("log_file", path)?);
+    if !path.is_absolute() {
+        return Err(format!(
+            "`log_file` must be an absolute path, since relative paths cannot be resolved \
+             against the worktree: '{}'",
+            path.display()
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            format!(
+                "failed to create the directory of `log_file` '{}': {err}",
+                path.display()
+            )
+        })?;
+    }
+    fs::File::create(&path)
+        .map_err(|err| format!("`log_file` '{}' is not writable: {err}", path.display()))?;
+
+    let mut args = vec![
+        "-c".into(),
+        TEE_SCRIPT.into(),
+        "sh".into(),
+        path.display().to_string(),
+        command.command,
+    ];
+    args.extend(command.args);
+    Ok(Command {
+        command: "sh".into(),
+        args,
+        env: command.env,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn command(program: &str, args: &[&str]) -> Command {
+        Command {
+            command: program.into(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: vec![],
+        }
+    }
+
+    #[test]
+    fn stderr_goes_to_the_log_and_zed() {
+        let log = env::temp_dir().join("zed-mcp-grafana-log-file-test.log");
+        fs::write(&log, "").unwrap();
+        let output = process::Command::new("sh")
+            .args(["-c", TEE_SCRIPT, "sh"])
+            .arg(&log)
+            .args(["sh", "-c", "echo out; echo err >&2"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "err\n");
+        assert_eq!(fs::read_to_string(&log).unwrap(), "err\n");
+        fs::remove_file(&log).ok();
+    }
+
+    #[test]
+    fn the_log_is_truncated_on_start() {
+        let log = env::temp_dir().join("zed-mcp-grafana-log-file-truncate.log");
+        fs::write(&log, "the previous run's logs\n").unwrap();
+        let command = redirect(
+            command("sh", &["-c", "echo this run >&2"]),
+            &log.display().to_string(),
+        )
+        .unwrap();
+        // Waiting for stderr to close waits for `tee` too.
+        let output = process::Command::new(&command.command)
+            .args(&command.args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(fs::read_to_string(&log).unwrap(), "this run\n");
+        fs::remove_file(&log).ok();
+    }
+
+    #[test]
+    fn unwritable_logs_are_refused() {
+        let log = env::temp_dir().join("zed-mcp-grafana-log-file-directory");
+        fs::create_dir_all(&log).unwrap();
+        let err = redirect(command("mcp-grafana", &[]), &log.display().to_string()).unwrap_err();
+        assert!(
+            err.starts_with(&format!("`log_file` '{}' is not writable", log.display())),
+            "{err}"
+        );
+        assert_eq!(
+            redirect(command("mcp-grafana", &[]), "logs/mcp-grafana.log").unwrap_err(),
+            "`log_file` must be an absolute path, since relative paths cannot be resolved \
+             against the worktree: 'logs/mcp-grafana.log'"
+        );
+        fs::remove_dir(&log).ok();
+    }
+}
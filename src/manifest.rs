@@ -0,0 +1,213 @@
+//! The manifest of the installed releases, which records their binaries and
+//! which of them is in use, and shows when a binary was corrupted after
+//! being installed.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use zed_extension_api::{Result, serde_json};
+
+use crate::{integrity, log};
+
+/// The file the manifest is kept in, in the extension's working directory.
+const FILE_NAME: &str = "manifest.json";
+
+/// The file a new manifest is written to before replacing [`FILE_NAME`],
+/// so that an interrupted write never leaves a truncated manifest.
+const TEMP_FILE_NAME: &str = "manifest.json.tmp";
+
+/// The installed releases, and the one each context server runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// The installed releases, by version directory.
+    #[serde(default)]
+    installs: BTreeMap<String, Install>,
+    /// The binary each context server runs, by context server ID.
+    #[serde(default)]
+    active: BTreeMap<String, String>,
+}
+
+/// A release installed in a version directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Install {
+    pub(crate) tag: String,
+    pub(crate) binary_path: String,
+    size: u64,
+    sha256: String,
+    /// Seconds since the Unix epoch.
+    installed_at: u64,
+}
+
+/// Whether an installed binary matches the manifest.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Check {
+    /// The binary matches the manifest.
+    Intact,
+    /// The manifest doesn't list the version directory, such as when
+    /// another machine sharing the directory installed it.
+    Missing,
+    /// The binary doesn't match the manifest, for this reason.
+    Corrupted(String),
+}
+
+impl Manifest {
+    /// Reads the manifest, or returns the one `regenerate` builds from the
+    /// installed version directories if it is missing or corrupted.
+    pub(crate) fn load(regenerate: impl FnOnce() -> Manifest) -> Self {
+        let contents = match fs::read(FILE_NAME) {
+            Ok(contents) => contents,
+            Err(_) => return Self::regenerate(regenerate),
+        };
+        match serde_json::from_slice(&contents) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                log(format!(
+                    "regenerating the corrupted '{FILE_NAME}' from the installed releases: {err}"
+                ));
+                Self::regenerate(regenerate)
+            }
+        }
+    }
+
+    fn regenerate(regenerate: impl FnOnce() -> Manifest) -> Self {
+        let manifest = regenerate();
+        manifest.save();
+        manifest
+    }
+
+    /// Writes the manifest, logging rather than failing if it can't be
+    /// written, since it can be regenerated.
+    pub(crate) fn save(&self) {
+        let written = serde_json::to_vec_pretty(self)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| fs::write(TEMP_FILE_NAME, contents).map_err(|err| err.to_string()))
+            .and_then(|()| fs::rename(TEMP_FILE_NAME, FILE_NAME).map_err(|err| err.to_string()));
+        if let Err(err) = written {
+            fs::remove_file(TEMP_FILE_NAME).ok();
+            log(format!("failed to write '{FILE_NAME}': {err}"));
+        }
+    }
+
+    /// Records release `tag`, installed in `version_dir` with the binary
+    /// `binary_path`, along with the binary's size and checksum.
+    ///
+    /// `installed_at` defaults to now.
+    pub(crate) fn record(
+        &mut self,
+        version_dir: &str,
+        tag: &str,
+        binary_path: &str,
+        installed_at: Option<SystemTime>,
+    ) -> Result<()> {
+        let binary = fs::read(binary_path)
+            .map_err(|err| format!("failed to read '{binary_path}': {err}"))?;
+        let installed_at = installed_at
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        self.installs.insert(
+            version_dir.into(),
+            Install {
+                tag: tag.into(),
+                binary_path: binary_path.into(),
+                size: binary.len() as u64,
+                sha256: integrity::sha256_hex(&binary),
+                installed_at,
+            },
+        );
+        Ok(())
+    }
+
+    /// Forgets the release installed in `version_dir`, and every other one
+    /// within it.
+    pub(crate) fn remove(&mut self, version_dir: &str) {
+        let nested = format!("{version_dir}/");
+        self.installs
+            .retain(|dir, _| dir != version_dir && !dir.starts_with(&nested));
+        self.active
+            .retain(|_, binary_path| !binary_path.starts_with(&nested));
+    }
+
+    /// Returns the releases installed directly in `dir`, by version
+    /// directory.
+    pub(crate) fn installs_in<'a>(
+        &'a self,
+        dir: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a Install)> + 'a {
+        self.installs
+            .iter()
+            .filter_map(move |(version_dir, install)| {
+                let (parent, _) = version_dir.rsplit_once('/')?;
+                (parent == dir).then_some((version_dir.as_str(), install))
+            })
+    }
+
+    /// Returns how long ago the release in `version_dir` was installed, if
+    /// it is listed.
+    pub(crate) fn age(&self, version_dir: &str) -> Option<Duration> {
+        let install = self.installs.get(version_dir)?;
+        let installed_at = UNIX_EPOCH + Duration::from_secs(install.installed_at);
+        SystemTime::now().duration_since(installed_at).ok()
+    }
+
+    /// Checks `binary_path`, installed in `version_dir`, against the
+    /// manifest.
+    ///
+    /// Only the size is compared unless `thorough` is set, since hashing
+    /// the binary takes a moment.
+    pub(crate) fn check(&self, version_dir: &str, binary_path: &str, thorough: bool) -> Check {
+        let Ok(stat) = fs::metadata(binary_path) else {
+            return Check::Corrupted("its binary is missing".into());
+        };
+        let Some(install) = self.installs.get(version_dir) else {
+            return Check::Missing;
+        };
+        if stat.len() != install.size {
+            return Check::Corrupted(format!(
+                "its binary is {} bytes instead of the {} it had when installed",
+                stat.len(),
+                install.size
+            ));
+        }
+        if thorough {
+            let Ok(binary) = fs::read(binary_path) else {
+                return Check::Corrupted("its binary can't be read".into());
+            };
+            if integrity::sha256_hex(&binary) != install.sha256 {
+                return Check::Corrupted(
+                    "the checksum of its binary differs from the one it had when installed".into(),
+                );
+            }
+        }
+        Check::Intact
+    }
+
+    /// Records that `context_server_id` runs `binary_path`, or a binary the
+    /// extension didn't install if it is `None`.
+    pub(crate) fn set_active(&mut self, context_server_id: &str, binary_path: Option<&str>) {
+        match binary_path {
+            Some(binary_path) => self
+                .active
+                .insert(context_server_id.into(), binary_path.into()),
+            None => self.active.remove(context_server_id),
+        };
+    }
+
+    /// Returns whether a context server other than `context_server_id`
+    /// runs the release installed in `version_dir`.
+    pub(crate) fn is_active_elsewhere(&self, version_dir: &str, context_server_id: &str) -> bool {
+        let nested = format!("{version_dir}/");
+        self.active
+            .iter()
+            .any(|(id, binary_path)| id != context_server_id && binary_path.starts_with(&nested))
+    }
+}
+
+/// Removes the manifest, returning whether there was one.
+pub(crate) fn clear() -> bool {
+    fs::remove_file(FILE_NAME).is_ok()
+}
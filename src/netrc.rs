@@ -0,0 +1,159 @@
+//! A parser for `.netrc` files.
+//!
+//! The file is a whitespace-separated sequence of tokens, so entries may be
+//! split across lines arbitrarily. Lines starting with `#` are comments, and
+//! `macdef` definitions (which run until the next blank line) are skipped.
+
+use crate::credentials::Secret;
+
+/// The credentials from a `.netrc` entry.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Entry {
+    pub(crate) login: Option<String>,
+    pub(crate) password: Option<Secret>,
+}
+
+/// Finds the entry for `host` in the contents of a `.netrc` file, falling
+/// back to the `default` entry if there is one.
+pub(crate) fn lookup(contents: &str, host: &str) -> Option<Entry> {
+    let mut matched = None;
+    let mut default = None;
+    // The entry currently being parsed, and whether it is the one for
+    // `host` (`Some(true)`), the default (`None`) or another host.
+    let mut current: Option<(Option<bool>, Entry)> = None;
+
+    let mut finish = |current: Option<(Option<bool>, Entry)>| match current {
+        Some((Some(true), entry)) if matched.is_none() => matched = Some(entry),
+        Some((None, entry)) if default.is_none() => default = Some(entry),
+        _ => {}
+    };
+
+    let mut tokens = tokens(contents);
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => {
+                finish(current.take());
+                let machine = tokens.next().unwrap_or_default();
+                current = Some((Some(machine.eq_ignore_ascii_case(host)), Entry::default()));
+            }
+            "default" => {
+                finish(current.take());
+                current = Some((None, Entry::default()));
+            }
+            "login" | "password" | "account" => {
+                let value = tokens.next().map(String::from);
+                if let Some((_, entry)) = &mut current {
+                    match token {
+                        "login" => entry.login = value,
+                        "password" => entry.password = value.map(Secret::from),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    finish(current);
+    matched.or(default)
+}
+
+/// Splits `contents` into tokens, dropping comments and macro definitions.
+fn tokens(contents: &str) -> impl Iterator<Item = &str> {
+    let mut in_macdef = false;
+    contents
+        .lines()
+        .filter(move |line| {
+            if in_macdef {
+                in_macdef = !line.trim().is_empty();
+                return false;
+            }
+            if line.split_whitespace().next() == Some("macdef") {
+                in_macdef = true;
+                return false;
+            }
+            !line.trim_start().starts_with('#')
+        })
+        .flat_map(str::split_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(login: &str, password: &str) -> Option<Entry> {
+        Some(Entry {
+            login: Some(login.into()),
+            password: Some(Secret::from(password.to_string())),
+        })
+    }
+
+    #[test]
+    fn finds_the_entry_for_the_host() {
+        let contents = "machine other.example.com login other password nope\n\
+                        machine grafana.example.com login admin password s3cret\n";
+        assert_eq!(
+            lookup(contents, "grafana.example.com"),
+            entry("admin", "s3cret")
+        );
+        assert_eq!(
+            lookup(contents, "GRAFANA.example.com"),
+            entry("admin", "s3cret")
+        );
+        assert_eq!(lookup(contents, "missing.example.com"), None);
+    }
+
+    #[test]
+    fn falls_back_to_the_default_entry() {
+        let contents = "default login anyone password fallback\n\
+                        machine grafana.example.com login admin password s3cret";
+        assert_eq!(
+            lookup(contents, "grafana.example.com"),
+            entry("admin", "s3cret")
+        );
+        assert_eq!(
+            lookup(contents, "other.example.com"),
+            entry("anyone", "fallback")
+        );
+    }
+
+    #[test]
+    fn handles_multi_line_entries_comments_and_macros() {
+        let contents = "# credentials\n\
+                        machine grafana.example.com\n\
+                        \tlogin admin\n\
+                        \taccount ignored\n\
+                        \tpassword s3cret\n\
+                        macdef init\n\
+                        machine fake.example.com login x password y\n\
+                        \n\
+                        machine next.example.com login next";
+        assert_eq!(
+            lookup(contents, "grafana.example.com"),
+            entry("admin", "s3cret")
+        );
+        assert_eq!(lookup(contents, "fake.example.com"), None);
+        assert_eq!(
+            lookup(contents, "next.example.com"),
+            Some(Entry {
+                login: Some("next".into()),
+                password: None,
+            })
+        );
+    }
+
+    #[test]
+    fn the_first_matching_entry_wins() {
+        let contents = "machine grafana.example.com login first password one\n\
+                        machine grafana.example.com login second password two";
+        assert_eq!(
+            lookup(contents, "grafana.example.com"),
+            entry("first", "one")
+        );
+    }
+
+    #[test]
+    fn passwords_are_redacted_when_formatted() {
+        let found = lookup("machine h login admin password s3cret", "h").unwrap();
+        assert!(!format!("{found:?}").contains("s3cret"));
+    }
+}
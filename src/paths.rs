@@ -0,0 +1,73 @@
+//! Helpers for file paths given in settings.
+
+use std::{env, fs, path::Path};
+
+use zed_extension_api::Result;
+
+/// Checks that the file at `path`, given in the `setting` setting, exists
+/// and is readable.
+///
+/// Paths must be absolute: the extension API doesn't expose the worktree
+/// root to context servers, so there is nothing to resolve relative paths
+/// against.
+pub(crate) fn check_readable_file(setting: &str, path: &str) -> Result<()> {
+    if !Path::new(path).is_absolute() {
+        return Err(format!(
+            "`{setting}` must be an absolute path, since relative paths cannot be resolved \
+             against the worktree: '{path}'"
+        ));
+    }
+    let metadata = fs::metadata(path)
+        .map_err(|err| format!("`{setting}` '{path}' is not accessible: {err}"))?;
+    if !metadata.is_file() {
+        return Err(format!("`{setting}` '{path}' is not a file"));
+    }
+    fs::File::open(path).map_err(|err| format!("`{setting}` '{path}' is not readable: {err}"))?;
+    Ok(())
+}
+
+/// Expands a leading `~/` in `path`, given in the `setting` setting, to the
+/// home directory.
+pub(crate) fn expand_home(setting: &str, path: &str) -> Result<String> {
+    let Some(rest) = path.strip_prefix("~/") else {
+        return Ok(path.into());
+    };
+    let home = env::var("HOME")
+        .map_err(|_| format!("cannot expand `~` in `{setting}`: HOME is not set"))?;
+    Ok(Path::new(&home).join(rest).display().to_string())
+}
+
+/// Checks that the file at `path`, given in the `setting` setting, exists
+/// and is executable.
+///
+/// Whether the file is executable can only be checked on Unix; elsewhere
+/// only its existence is checked.
+pub(crate) fn check_executable_file(setting: &str, path: &str) -> Result<()> {
+    if !Path::new(path).is_absolute() {
+        return Err(format!(
+            "`{setting}` must be an absolute path, since relative paths cannot be resolved \
+             against the worktree: '{path}'"
+        ));
+    }
+    let metadata = fs::metadata(path)
+        .map_err(|err| format!("`{setting}` '{path}' is not accessible: {err}"))?;
+    if !metadata.is_file() {
+        return Err(format!("`{setting}` '{path}' is not a file"));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(format!("`{setting}` '{path}' is not executable"));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the absolute path of `path`, relative to the extension's working
+/// directory, for passing to other processes.
+pub(crate) fn in_work_dir(path: &str) -> Result<String> {
+    let work_dir = env::current_dir()
+        .map_err(|err| format!("failed to find the extension's working directory: {err}"))?;
+    Ok(work_dir.join(path).display().to_string())
+}
@@ -0,0 +1,90 @@
+//! Precedence between values from the settings and the environment.
+
+use serde::Deserialize;
+use zed_extension_api::Result;
+
+/// Which of the settings and the environment wins when a value is set in
+/// both.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Precedence {
+    /// Values in the settings override environment variables.
+    #[default]
+    Settings,
+    /// Environment variables override values in the settings.
+    Env,
+}
+
+impl Precedence {
+    /// Picks between a value from the settings and one from the environment.
+    pub(crate) fn pick<T>(self, setting: Option<T>, env: Option<T>) -> Option<T> {
+        match self {
+            Self::Settings => setting.or(env),
+            Self::Env => env.or(setting),
+        }
+    }
+
+    /// Like [`Precedence::pick`], but lazily computes the values so that
+    /// the losing source is never consulted.
+    pub(crate) fn pick_with<T>(
+        self,
+        setting: impl FnOnce() -> Result<Option<T>>,
+        env: impl FnOnce() -> Result<Option<T>>,
+    ) -> Result<Option<T>> {
+        match self {
+            Self::Settings => match setting()? {
+                Some(value) => Ok(Some(value)),
+                None => env(),
+            },
+            Self::Env => match env()? {
+                Some(value) => Ok(Some(value)),
+                None => setting(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_prefers_the_configured_source() {
+        let cases = [
+            (
+                Precedence::Settings,
+                Some("setting"),
+                Some("env"),
+                Some("setting"),
+            ),
+            (Precedence::Settings, None, Some("env"), Some("env")),
+            (Precedence::Env, Some("setting"), Some("env"), Some("env")),
+            (Precedence::Env, Some("setting"), None, Some("setting")),
+            (Precedence::Env, None, None, None),
+        ];
+        for (precedence, setting, env, expected) in cases {
+            assert_eq!(precedence.pick(setting, env), expected, "{precedence:?}");
+        }
+    }
+
+    #[test]
+    fn pick_with_never_consults_the_losing_source() {
+        let unreachable = || -> Result<Option<&str>> { panic!("the losing source was consulted") };
+        assert_eq!(
+            Precedence::Settings.pick_with(|| Ok(Some("setting")), unreachable),
+            Ok(Some("setting"))
+        );
+        assert_eq!(
+            Precedence::Env.pick_with(unreachable, || Ok(Some("env"))),
+            Ok(Some("env"))
+        );
+        assert_eq!(
+            Precedence::Env.pick_with(|| Ok(Some("setting")), || Ok(None)),
+            Ok(Some("setting"))
+        );
+        assert_eq!(
+            Precedence::Settings.pick_with(|| Err("no file".into()), || Ok(Some("env"))),
+            Err::<Option<&str>, _>("no file".into())
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Pre-flight validation of the Grafana URL and credentials.
+
+use zed_extension_api::{
+    Result,
+    http_client::{HttpMethod, HttpRequest, RedirectPolicy},
+    serde_json,
+};
+
+use crate::credentials::Credentials;
+
+/// Checks that Grafana is reachable at `grafana_url` and accepts
+/// `credentials`, by requesting `/api/user` (or `/api/health` when
+/// connecting anonymously), with `extra_headers` added to the request.
+///
+/// Note that the extension API's HTTP client has no timeout setting, so a
+/// host which never responds is bounded only by Zed's own timeouts.
+pub(crate) fn check(
+    grafana_url: &str,
+    credentials: &Credentials,
+    extra_headers: &[(String, String)],
+) -> Result<()> {
+    let path = match credentials {
+        Credentials::Anonymous => "api/health",
+        _ => "api/user",
+    };
+    let url = format!("{}/{path}", grafana_url.trim_end_matches('/'));
+    let response = HttpRequest::builder()
+        .method(HttpMethod::Get)
+        .url(&url)
+        .headers(credentials.headers())
+        .headers(extra_headers.iter().cloned())
+        .header("Accept", "application/json")
+        .redirect_policy(RedirectPolicy::FollowLimit(5))
+        .build()?
+        .fetch()
+        .map_err(|err| describe_failure(grafana_url, &err))?;
+
+    let body: serde_json::Value = serde_json::from_slice(&response.body).map_err(|_| {
+        format!(
+            "Grafana pre-flight check failed: {url} did not return JSON; check that \
+             `grafana_url` points at Grafana and not at a login page or proxy"
+        )
+    })?;
+    if let Some(message) = body.get("message").and_then(|m| m.as_str())
+        && body.get("id").is_none()
+        && body.get("database").is_none()
+    {
+        return Err(format!(
+            "Grafana pre-flight check failed: Grafana rejected the request to {url}: {message}; \
+             check that the configured credentials are valid and not expired"
+        ));
+    }
+    Ok(())
+}
+
+/// Turns an error from the HTTP client into a message saying whether the
+/// URL, TLS or the credentials are likely at fault.
+fn describe_failure(grafana_url: &str, err: &str) -> String {
+    let lower = err.to_ascii_lowercase();
+    let hint = if ["certificate", "tls", "ssl", "x509", "handshake"]
+        .iter()
+        .any(|needle| lower.contains(needle))
+    {
+        "TLS verification failed; check `tls_ca_file`, or `tls_skip_verify` for local instances"
+    } else if ["401", "403", "unauthorized", "forbidden"]
+        .iter()
+        .any(|needle| lower.contains(needle))
+    {
+        "the credentials were rejected; check that the token or password is valid and not expired"
+    } else if lower.contains("404") {
+        "the Grafana API was not found; check that `grafana_url` includes any sub-path Grafana is served under"
+    } else {
+        "Grafana could not be reached; check `grafana_url` and your network connection"
+    };
+    format!("Grafana pre-flight check against {grafana_url} failed: {hint} ({err})")
+}
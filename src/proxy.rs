@@ -0,0 +1,150 @@
+//! Proxy settings for the server process.
+
+use url::Url;
+
+use crate::{debug_log, environment::Environment, settings::GrafanaContextServerSettings};
+
+/// Returns the proxy variables to set for the server, in both their lower-
+/// and upper-case forms.
+///
+/// Each of `http_proxy`, `https_proxy` and `no_proxy` is picked from the
+/// settings or the extension's environment according to
+/// `settings_precedence`. Variables set in neither are left out.
+pub(crate) fn env(
+    settings: &GrafanaContextServerSettings,
+    process_env: &Environment,
+) -> Vec<(String, String)> {
+    let proxies = [
+        ("http_proxy", &settings.http_proxy),
+        ("https_proxy", &settings.https_proxy),
+        ("no_proxy", &settings.no_proxy),
+    ];
+    let mut env = vec![];
+    let mut applied = vec![];
+    for (name, setting) in proxies {
+        let upper = name.to_ascii_uppercase();
+        let from_env = process_env.var(name).or_else(|| process_env.var(&upper));
+        let Some(value) = settings.settings_precedence.pick(setting.clone(), from_env) else {
+            continue;
+        };
+        applied.push(format!("{name}={}", describe(name, &value)));
+        env.push((name.to_string(), value.clone()));
+        env.push((upper, value));
+    }
+    if !applied.is_empty() {
+        debug_log(format_args!("using proxy settings {}", applied.join(" ")));
+    }
+    env
+}
+
+/// Returns whether the proxy URL `value` contains credentials.
+pub(crate) fn has_credentials(value: &str) -> bool {
+    Url::parse(value).is_ok_and(|url| !url.username().is_empty() || url.password().is_some())
+}
+
+/// Describes a proxy setting for logging: the host of a proxy URL, or the
+/// list of hosts in `no_proxy`.
+fn describe(name: &str, value: &str) -> String {
+    if name == "no_proxy" {
+        return value.to_string();
+    }
+    match Url::parse(value) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            (None, _) => "<invalid>".into(),
+        },
+        Err(_) => "<invalid>".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zed_extension_api::serde_json::{Value, json};
+
+    use super::*;
+    use crate::settings;
+
+    fn proxy_env(value: Value, vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        env(
+            &settings::parse(value).unwrap(),
+            &Environment::with_vars(vars),
+        )
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.into(), value.into())
+    }
+
+    #[test]
+    fn settings_override_the_environment() {
+        assert_eq!(
+            proxy_env(
+                json!({ "https_proxy": "http://setting:3128" }),
+                &[("https_proxy", "http://env:3128")]
+            ),
+            [
+                pair("https_proxy", "http://setting:3128"),
+                pair("HTTPS_PROXY", "http://setting:3128")
+            ]
+        );
+    }
+
+    #[test]
+    fn unset_settings_fall_back_to_either_case_of_the_environment() {
+        assert_eq!(
+            proxy_env(
+                json!({}),
+                &[
+                    ("HTTP_PROXY", "http://upper:3128"),
+                    ("no_proxy", "localhost")
+                ]
+            ),
+            [
+                pair("http_proxy", "http://upper:3128"),
+                pair("HTTP_PROXY", "http://upper:3128"),
+                pair("no_proxy", "localhost"),
+                pair("NO_PROXY", "localhost")
+            ]
+        );
+        // The lower-case form wins when both are set.
+        assert_eq!(
+            proxy_env(
+                json!({}),
+                &[
+                    ("http_proxy", "http://lower:3128"),
+                    ("HTTP_PROXY", "http://upper:3128")
+                ]
+            ),
+            [
+                pair("http_proxy", "http://lower:3128"),
+                pair("HTTP_PROXY", "http://lower:3128")
+            ]
+        );
+    }
+
+    #[test]
+    fn the_environment_wins_with_env_precedence() {
+        assert_eq!(
+            proxy_env(
+                json!({
+                    "http_proxy": "http://setting:3128",
+                    "no_proxy": "setting.example.com",
+                    "settings_precedence": "env",
+                }),
+                &[("http_proxy", "http://env:3128")]
+            ),
+            [
+                pair("http_proxy", "http://env:3128"),
+                pair("HTTP_PROXY", "http://env:3128"),
+                pair("no_proxy", "setting.example.com"),
+                pair("NO_PROXY", "setting.example.com")
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_is_exported_without_proxies() {
+        assert!(proxy_env(json!({}), &[]).is_empty());
+    }
+}
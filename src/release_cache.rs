@@ -0,0 +1,107 @@
+//! Persisting the result of the latest-release lookup between sessions.
+
+use std::{
+    fs,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use zed_extension_api::serde_json;
+
+use crate::{binary::Channel, log};
+
+/// The file the lookup is persisted to, in the extension's working
+/// directory.
+const CACHE_FILE: &str = "latest-release.json";
+
+/// The outcome of a latest-release lookup.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CachedRelease {
+    /// The tag of the release which was selected.
+    pub(crate) tag: String,
+    /// The asset which was selected for the platform.
+    pub(crate) asset_name: String,
+    /// The download URL of that asset.
+    pub(crate) download_url: String,
+    /// The repository the release was looked up in.
+    #[serde(default)]
+    pub(crate) repo: String,
+    /// The channel the release was looked up on.
+    #[serde(default)]
+    pub(crate) channel: Channel,
+    /// Whether pre-releases were allowed in the lookup.
+    pub(crate) allow_prerelease: bool,
+    /// When the lookup was made, in seconds since the Unix epoch.
+    checked_at: u64,
+}
+
+impl CachedRelease {
+    pub(crate) fn new(
+        tag: String,
+        asset_name: String,
+        download_url: String,
+        repo: String,
+        channel: Channel,
+        allow_prerelease: bool,
+    ) -> Self {
+        Self {
+            tag,
+            asset_name,
+            download_url,
+            repo,
+            channel,
+            allow_prerelease,
+            checked_at: now(),
+        }
+    }
+}
+
+/// Returns the persisted lookup for `asset_name` in `repo` on `channel`
+/// with `allow_prerelease`, unless it is older than `max_age`.
+///
+/// A missing or unreadable cache file counts as no lookup.
+pub(crate) fn load(
+    asset_name: &str,
+    repo: &str,
+    channel: Channel,
+    allow_prerelease: bool,
+    max_age: Duration,
+) -> Option<CachedRelease> {
+    let contents = fs::read(CACHE_FILE).ok()?;
+    let cached: CachedRelease = match serde_json::from_slice(&contents) {
+        Ok(cached) => cached,
+        Err(err) => {
+            log(format!("ignoring the corrupted '{CACHE_FILE}': {err}"));
+            fs::remove_file(CACHE_FILE).ok();
+            return None;
+        }
+    };
+    let fresh = now().saturating_sub(cached.checked_at) < max_age.as_secs();
+    (fresh
+        && cached.asset_name == asset_name
+        && cached.repo == repo
+        && cached.channel == channel
+        && cached.allow_prerelease == allow_prerelease)
+        .then_some(cached)
+}
+
+/// Persists `release`, logging rather than failing if it can't be written.
+pub(crate) fn store(release: &CachedRelease) {
+    let written = serde_json::to_vec(release)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| fs::write(CACHE_FILE, contents).map_err(|err| err.to_string()));
+    if let Err(err) = written {
+        log(format!("failed to write '{CACHE_FILE}': {err}"));
+    }
+}
+
+/// Forgets the persisted lookup, returning whether there was one.
+pub(crate) fn clear() -> bool {
+    fs::remove_file(CACHE_FILE).is_ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
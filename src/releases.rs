@@ -0,0 +1,381 @@
+//! Looking up the releases of the Grafana MCP server, and selecting one by
+//! a semver range.
+
+use std::{
+    cmp::Reverse,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use zed_extension_api::{
+    self as zed, Result,
+    http_client::{HttpMethod, HttpRequest, RedirectPolicy},
+    serde_json,
+};
+
+use crate::{credentials::Secret, retry};
+
+/// The API of github.com.
+const GITHUB_API: &str = "https://api.github.com";
+
+/// A GitHub repository to look up releases in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Repo {
+    /// The owner and name of the repository, such as `grafana/mcp-grafana`.
+    name: String,
+    /// The base URL of the GitHub Enterprise API serving the repository,
+    /// such as `https://github.example.com/api/v3`.
+    api_base: Option<String>,
+}
+
+impl Repo {
+    /// Returns the repository `name`, given in the `setting` setting, on
+    /// github.com or the GitHub Enterprise API at `api_base`.
+    pub(crate) fn new(setting: &str, name: &str, api_base: Option<&str>) -> Result<Self> {
+        let name = name.trim();
+        let valid = name.split_once('/').is_some_and(|(owner, repo)| {
+            !owner.is_empty() && !repo.is_empty() && !repo.contains('/')
+        });
+        if !valid {
+            return Err(format!(
+                "invalid `{setting}` '{name}'; expected a GitHub repository such as \
+                 'grafana/mcp-grafana'"
+            ));
+        }
+        let api_base = api_base
+            .map(|api_base| {
+                let api_base = api_base.trim().trim_end_matches('/');
+                if api_base.starts_with("https://") || api_base.starts_with("http://") {
+                    Ok(api_base.to_string())
+                } else {
+                    Err(format!(
+                        "invalid `github_api_base` '{api_base}'; expected a URL such as \
+                         'https://github.example.com/api/v3'"
+                    ))
+                }
+            })
+            .transpose()?;
+        Ok(Self {
+            name: name.into(),
+            api_base,
+        })
+    }
+
+    fn api(&self) -> &str {
+        self.api_base.as_deref().unwrap_or(GITHUB_API)
+    }
+}
+
+impl fmt::Display for Repo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.api_base {
+            Some(api_base) => write!(f, "{} (at {api_base})", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A release as returned by GitHub's REST API.
+#[derive(Deserialize)]
+struct ApiRelease {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    assets: Vec<ApiAsset>,
+}
+
+#[derive(Deserialize)]
+struct ApiAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Returns the latest release of `repo` with assets, including
+/// pre-releases if `allow_prerelease` is set.
+///
+/// Without a `token` this uses the extension API's anonymous lookup, and
+/// with one, or for GitHub Enterprise, GitHub's REST API. The releases the
+/// latter lists are sorted with [`sort_newest_first`], so the latest is the
+/// highest version rather than the most recently published.
+pub(crate) fn latest(
+    repo: &Repo,
+    allow_prerelease: bool,
+    token: Option<&Secret>,
+) -> Result<zed::GithubRelease> {
+    if token.is_none() && repo.api_base.is_none() {
+        return retry::with_backoff("looking up the latest release", || {
+            zed::latest_github_release(
+                &repo.name,
+                zed::GithubReleaseOptions {
+                    require_assets: true,
+                    pre_release: allow_prerelease,
+                },
+            )
+        })
+        .map_err(|err| {
+            format!(
+                "failed to look up the latest release of {repo}: {}",
+                describe_failure(&err, None)
+            )
+        });
+    }
+    let mut releases = list(repo, token)?;
+    sort_newest_first(&mut releases);
+    releases
+        .into_iter()
+        .filter(|release| !release.assets.is_empty())
+        .find(|release| {
+            allow_prerelease || parse_tag(&release.version).is_none_or(|v| v.pre.is_empty())
+        })
+        .ok_or_else(|| format!("{repo} has no releases with assets"))
+}
+
+/// Returns the release of `repo` tagged `tag`, using the same API as
+/// [`latest`].
+pub(crate) fn by_tag(repo: &Repo, tag: &str, token: Option<&Secret>) -> Result<zed::GithubRelease> {
+    if token.is_none() && repo.api_base.is_none() {
+        return retry::with_backoff("looking up the release", || {
+            zed::github_release_by_tag_name(&repo.name, tag)
+        })
+        .map_err(|err| describe_failure(&err, None));
+    }
+    let body = get(
+        &format!("{}/repos/{}/releases/tags/{tag}", repo.api(), repo.name),
+        "looking up the release",
+        token,
+    )?;
+    let release: ApiRelease = serde_json::from_slice(&body)
+        .map_err(|err| format!("unexpected response from GitHub: {err}"))?;
+    Ok(release.into())
+}
+
+/// Returns the published releases of `repo`, newest first.
+///
+/// Only the most recent 100 releases are listed.
+pub(crate) fn list(repo: &Repo, token: Option<&Secret>) -> Result<Vec<zed::GithubRelease>> {
+    let url = format!("{}/repos/{}/releases?per_page=100", repo.api(), repo.name);
+    let body = get(&url, "listing the releases", token)
+        .map_err(|err| format!("failed to list the releases of {repo}: {err}"))?;
+    let releases: Vec<ApiRelease> = serde_json::from_slice(&body).map_err(|err| {
+        format!("failed to list the releases of {repo}: unexpected response from GitHub: {err}")
+    })?;
+    Ok(releases
+        .into_iter()
+        .filter(|release| !release.draft)
+        .map(zed::GithubRelease::from)
+        .collect())
+}
+
+impl From<ApiRelease> for zed::GithubRelease {
+    fn from(release: ApiRelease) -> Self {
+        Self {
+            version: release.tag_name,
+            assets: release
+                .assets
+                .into_iter()
+                .map(|asset| zed::GithubReleaseAsset {
+                    name: asset.name,
+                    download_url: asset.browser_download_url,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Requests `url` from GitHub's REST API, with `token` if given, and
+/// returns the response body.
+///
+/// `what` describes the request in retry logs.
+fn get(url: &str, what: &str, token: Option<&Secret>) -> Result<Vec<u8>> {
+    let mut request = HttpRequest::builder()
+        .method(HttpMethod::Get)
+        .url(url)
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "zed-mcp-grafana")
+        .redirect_policy(RedirectPolicy::FollowLimit(5));
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {}", token.expose()));
+    }
+    let request = request.build()?;
+    let response = retry::with_backoff(what, || request.fetch())
+        .map_err(|err| describe_failure(&err, None))?;
+
+    // Errors come back as an object with a message, rather than a list or
+    // a release.
+    if let Ok(error) = serde_json::from_slice::<ApiError>(&response.body) {
+        let reset = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("x-ratelimit-reset"))
+            .and_then(|(_, value)| value.parse().ok());
+        return Err(describe_failure(&error.message, reset));
+    }
+    Ok(response.body)
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+/// Adds advice to `err` if it says that GitHub's rate limit was exceeded,
+/// including when the limit resets if `reset` gives that, in seconds since
+/// the Unix epoch.
+fn describe_failure(err: &str, reset: Option<u64>) -> String {
+    if !err.to_ascii_lowercase().contains("rate limit") {
+        return err.into();
+    }
+    let reset = match reset {
+        Some(reset) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs());
+            format!(
+                "; it resets in {} minutes",
+                reset.saturating_sub(now).div_ceil(60)
+            )
+        }
+        None => String::new(),
+    };
+    format!(
+        "GitHub's API rate limit was exceeded{reset}; set `github_token`, GITHUB_TOKEN or \
+         GH_TOKEN to a GitHub token to raise the limit ({err})"
+    )
+}
+
+/// Parses a release tag such as `v0.2.1` or `v1.0.0-rc.1` as a version.
+pub(crate) fn parse_tag(tag: &str) -> Option<Version> {
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()
+}
+
+/// Sorts `releases` by version, newest first, comparing them as semver
+/// rather than as strings, so that `v0.10.0` is newer than `v0.9.1`.
+///
+/// Releases whose tags aren't semver versions keep their order, after the
+/// rest.
+pub(crate) fn sort_newest_first(releases: &mut [zed::GithubRelease]) {
+    releases.sort_by_cached_key(|release| Reverse(parse_tag(&release.version)));
+}
+
+/// Returns the newest of `tags` which satisfies `req`, according to
+/// [`matches`].
+///
+/// Tags which aren't semver versions are ignored.
+pub(crate) fn newest_matching<'a>(
+    req: &VersionReq,
+    tags: impl IntoIterator<Item = &'a str>,
+    allow_prerelease: bool,
+) -> Option<&'a str> {
+    tags.into_iter()
+        .filter_map(|tag| Some((parse_tag(tag)?, tag)))
+        .filter(|(version, _)| matches(req, version, allow_prerelease))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag)
+}
+
+/// Returns whether `version` satisfies `req`.
+///
+/// As in Cargo, pre-releases only match if `req` names a pre-release of
+/// the same version, such as `>=1.0.0-rc.1`, unless `allow_prerelease` is
+/// set, in which case they match if the version they lead up to does.
+pub(crate) fn matches(req: &VersionReq, version: &Version, allow_prerelease: bool) -> bool {
+    req.matches(version)
+        || (allow_prerelease
+            && !version.pre.is_empty()
+            && req.matches(&Version::new(version.major, version.minor, version.patch)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str) -> zed::GithubRelease {
+        zed::GithubRelease {
+            version: tag.into(),
+            assets: vec![],
+        }
+    }
+
+    fn sorted(tags: &[&str]) -> Vec<String> {
+        let mut releases: Vec<_> = tags.iter().map(|tag| release(tag)).collect();
+        sort_newest_first(&mut releases);
+        releases
+            .into_iter()
+            .map(|release| release.version)
+            .collect()
+    }
+
+    #[test]
+    fn sorts_by_semver_newest_first() {
+        let cases: &[(&[&str], &[&str])] = &[
+            (&["v0.9.1", "v0.10.0"], &["v0.10.0", "v0.9.1"]),
+            (
+                &["v0.10.0", "v0.9.2", "v0.9.10"],
+                &["v0.10.0", "v0.9.10", "v0.9.2"],
+            ),
+            (&["v1.0.0-rc.1", "v1.0.0"], &["v1.0.0", "v1.0.0-rc.1"]),
+            (
+                &["v1.0.0-rc.2", "v1.0.0-rc.10"],
+                &["v1.0.0-rc.10", "v1.0.0-rc.2"],
+            ),
+            (
+                &["v1.0.0-alpha", "v1.0.0-beta", "v0.9.9"],
+                &["v1.0.0-beta", "v1.0.0-alpha", "v0.9.9"],
+            ),
+            (&["0.2.0", "v0.3.0"], &["v0.3.0", "0.2.0"]),
+            (
+                &["nightly-2", "v0.2.0", "nightly-1", "v0.3.0"],
+                &["v0.3.0", "v0.2.0", "nightly-2", "nightly-1"],
+            ),
+        ];
+        for (tags, expected) in cases {
+            assert_eq!(sorted(tags), *expected, "sorting {tags:?}");
+        }
+    }
+
+    #[test]
+    fn parses_go_style_tags() {
+        assert_eq!(parse_tag("v0.2.5"), Some(Version::new(0, 2, 5)));
+        assert_eq!(parse_tag("0.2.5"), Some(Version::new(0, 2, 5)));
+        assert!(parse_tag("v1.0.0-rc.1").is_some_and(|version| !version.pre.is_empty()));
+        assert_eq!(parse_tag("v0.2"), None);
+        assert_eq!(parse_tag("nightly"), None);
+    }
+
+    #[test]
+    fn matches_prereleases_only_when_allowed() {
+        let cases = [
+            (">=0.2", "v0.3.0", false, true),
+            (">=0.2", "v1.0.0-rc.1", false, false),
+            (">=0.2", "v1.0.0-rc.1", true, true),
+            (">=1.0.0-rc.1", "v1.0.0-rc.2", false, true),
+            ("^0.2", "v0.3.0-rc.1", true, false),
+            ("0.2.x", "v0.2.10", false, true),
+        ];
+        for (req, tag, allow_prerelease, expected) in cases {
+            let req = VersionReq::parse(req).unwrap();
+            let version = parse_tag(tag).unwrap();
+            assert_eq!(
+                matches(&req, &version, allow_prerelease),
+                expected,
+                "{req} matching {tag} with allow_prerelease {allow_prerelease}"
+            );
+        }
+    }
+
+    #[test]
+    fn picks_newest_matching_tag() {
+        let tags = ["v0.9.1", "v0.10.0", "v0.10.1-rc.1", "nightly", "v0.2.5"];
+        let newest = |req: &str, allow_prerelease| {
+            newest_matching(&VersionReq::parse(req).unwrap(), tags, allow_prerelease)
+        };
+        assert_eq!(newest(">=0.9", false), Some("v0.10.0"));
+        assert_eq!(newest(">=0.9", true), Some("v0.10.1-rc.1"));
+        assert_eq!(newest("<0.10", false), Some("v0.9.1"));
+        assert_eq!(newest("^0.2", false), Some("v0.2.5"));
+        assert_eq!(newest(">=2", true), None);
+    }
+}
@@ -0,0 +1,230 @@
+//! Using an already running Grafana MCP server which serves streamable
+//! HTTP, through a bridge that Zed talks to over stdin and stdout.
+
+use zed_extension_api::{
+    self as zed, Command, Result,
+    http_client::{HttpMethod, HttpRequest, RedirectPolicy},
+};
+
+use crate::{
+    docker::Runtime, environment::Environment, grafana_url, headers, log_file, paths, proxy,
+    settings::GrafanaContextServerSettings, subprocess,
+};
+
+/// The bridge run unless `bridge_command` is set, which is the
+/// `mcp-remote` package run with `npx`.
+const DEFAULT_BRIDGE: &[&str] = &["npx", "-y", "mcp-remote"];
+
+/// The variables from the login shell and `env_file` which are passed to
+/// the bridge, so that Node.js and npm can be found and configured. The
+/// rest, such as Grafana credentials, are meant for a local server.
+const BRIDGE_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+];
+
+/// Prefixes of further variables passed to the bridge; see
+/// [`BRIDGE_ENV_VARS`].
+const BRIDGE_ENV_PREFIXES: &[&str] = &["NODE_", "NPM_CONFIG_", "npm_config_", "MCP_REMOTE_"];
+
+/// Returns the command running the bridge to the server at `remote_url`,
+/// after checking that the bridge is installed and the server reachable.
+///
+/// The bridge is given the URL, followed by `--header Name:value` for each
+/// of `remote_headers`, as `mcp-remote` expects. Its stderr is written to
+/// `log_file`, if set.
+pub(crate) fn command(
+    remote_url: &str,
+    settings: &GrafanaContextServerSettings,
+    process_env: &Environment,
+) -> Result<Command> {
+    let conflict = [
+        ("grafana_url", settings.grafana_url.is_some()),
+        ("cloud_stack", settings.cloud_stack.is_some()),
+        ("binary_path", settings.binary_path.is_some()),
+        ("archive_path", settings.archive_path.is_some()),
+        ("dev_path", settings.dev_path.is_some()),
+        ("runtime", settings.runtime == Runtime::Docker),
+        ("docker_image", settings.docker_image.is_some()),
+    ]
+    .into_iter()
+    .find_map(|(setting, set)| set.then_some(setting));
+    if let Some(setting) = conflict {
+        return Err(format!(
+            "`{setting}` can't be combined with `remote_url`, which uses a server that is \
+             already running instead"
+        ));
+    }
+    let url = remote_url.trim();
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!(
+            "invalid `remote_url` '{url}'; expected an http:// or https:// URL"
+        ));
+    }
+    headers::validate("remote_headers", &settings.remote_headers)?;
+    if !settings.allow_insecure
+        && !settings.remote_headers.is_empty()
+        && let Some(host) = grafana_url::insecure_host(url)
+    {
+        return Err(format!(
+            "refusing to send `remote_headers` to '{host}' over plain http://; use an https:// \
+             URL, or set `allow_insecure` to true if this is intended"
+        ));
+    }
+
+    let (platform, _) = zed::current_platform();
+    let bridge = match &settings.bridge_command {
+        Some(bridge) => bridge.clone(),
+        None => DEFAULT_BRIDGE.iter().map(|arg| arg.to_string()).collect(),
+    };
+    let Some((program, bridge_args)) = bridge.split_first() else {
+        return Err("`bridge_command` must not be empty".into());
+    };
+    let program = find_bridge(program, platform, settings.bridge_command.is_some())?;
+    check_reachable(url, &headers::pairs(&settings.remote_headers))?;
+
+    let mut args = bridge_args.to_vec();
+    args.push(url.into());
+    for (name, value) in headers::pairs(&settings.remote_headers) {
+        args.extend(["--header".into(), format!("{name}:{value}")]);
+    }
+    let mut env = bridge_env(process_env.loaded_vars());
+    env.extend(proxy::env(settings, process_env));
+    let command = Command {
+        command: program,
+        args,
+        env,
+    };
+    match &settings.log_file {
+        Some(path) => log_file::redirect(command, path),
+        None => Ok(command),
+    }
+}
+
+/// Returns the `loaded_vars` the bridge needs; see [`BRIDGE_ENV_VARS`].
+fn bridge_env(loaded_vars: Vec<(&str, &str)>) -> Vec<(String, String)> {
+    loaded_vars
+        .into_iter()
+        .filter(|(key, _)| {
+            BRIDGE_ENV_VARS.contains(key)
+                || BRIDGE_ENV_PREFIXES
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix))
+        })
+        .map(|(key, value)| (key.into(), value.into()))
+        .collect()
+}
+
+/// Returns the path of the bridge `program`, which is looked up on `PATH`
+/// unless it is a path itself.
+fn find_bridge(program: &str, platform: zed::Os, configured: bool) -> Result<String> {
+    if program.contains(['/', '\\']) {
+        let program = paths::expand_home("bridge_command", program)?;
+        paths::check_executable_file("bridge_command", &program)?;
+        return Ok(program);
+    }
+    subprocess::find_on_path(program, platform).ok_or_else(|| {
+        if configured {
+            format!("the bridge '{program}' from `bridge_command` isn't on PATH")
+        } else {
+            format!(
+                "`remote_url` is set, but {program} isn't on PATH to run the mcp-remote bridge; \
+                 install Node.js, or set `bridge_command` to another bridge"
+            )
+        }
+    })
+}
+
+/// Checks that a server answers at `url`, with `headers` added to the
+/// request.
+///
+/// This sends a `HEAD` request, which doesn't start an MCP session. A
+/// server which doesn't allow `HEAD` still answered, so that isn't an
+/// error.
+fn check_reachable(url: &str, headers: &[(String, String)]) -> Result<()> {
+    let result = HttpRequest::builder()
+        .method(HttpMethod::Head)
+        .url(url)
+        .headers(headers.iter().cloned())
+        .redirect_policy(RedirectPolicy::FollowLimit(5))
+        .build()?
+        .fetch();
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) if is_method_not_allowed(&err) => Ok(()),
+        Err(err) => Err(describe_failure(url, &err)),
+    }
+}
+
+/// Returns whether the HTTP client's error `err` is a response rejecting
+/// the request's method.
+fn is_method_not_allowed(err: &str) -> bool {
+    let lower = err.to_ascii_lowercase();
+    lower.contains("405") || lower.contains("method not allowed")
+}
+
+/// Turns an error from the HTTP client into a message saying whether the
+/// URL, TLS or `remote_headers` are likely at fault.
+fn describe_failure(url: &str, err: &str) -> String {
+    let lower = err.to_ascii_lowercase();
+    let hint = if ["certificate", "tls", "ssl", "x509", "handshake"]
+        .iter()
+        .any(|needle| lower.contains(needle))
+    {
+        "TLS verification failed"
+    } else if ["401", "403", "unauthorized", "forbidden"]
+        .iter()
+        .any(|needle| lower.contains(needle))
+    {
+        "it rejected the request; check `remote_headers`"
+    } else if lower.contains("404") {
+        "it has no MCP endpoint there; check that `remote_url` includes the path, such as /mcp"
+    } else {
+        "it could not be reached; check `remote_url` and your network connection"
+    };
+    format!("the remote MCP server at {url} is unavailable: {hint} ({err})")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bridge_env_leaves_out_credentials() {
+        let loaded = vec![
+            ("GRAFANA_SERVICE_ACCOUNT_TOKEN", "glsa_secret"),
+            ("GRAFANA_URL", "https://grafana.example.com"),
+            ("HOME", "/home/me"),
+            ("MCP_REMOTE_CONFIG_DIR", "/home/me/.mcp-auth"),
+            ("NODE_EXTRA_CA_CERTS", "/etc/ca.pem"),
+            ("PATH", "/usr/bin"),
+            ("npm_config_registry", "https://npm.example.com"),
+        ];
+        let keys: Vec<String> = bridge_env(loaded).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(
+            keys,
+            [
+                "HOME",
+                "MCP_REMOTE_CONFIG_DIR",
+                "NODE_EXTRA_CA_CERTS",
+                "PATH",
+                "npm_config_registry"
+            ]
+        );
+    }
+
+    #[test]
+    fn method_not_allowed_means_the_server_answered() {
+        assert!(is_method_not_allowed("status 405 Method Not Allowed"));
+        assert!(!is_method_not_allowed("status 404 Not Found"));
+        assert!(!is_method_not_allowed("connection refused"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Retrying network operations which fail transiently.
+
+use std::{
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use zed_extension_api::Result;
+
+use crate::log;
+
+/// How often, and how far apart, to attempt an operation.
+pub(crate) struct RetryPolicy {
+    /// The total number of attempts, including the first.
+    pub(crate) attempts: u32,
+    /// The delay before the first retry, which doubles for each retry
+    /// after it.
+    pub(crate) base_delay: Duration,
+}
+
+/// The policy of [`with_backoff`].
+const DEFAULT_POLICY: RetryPolicy = RetryPolicy {
+    attempts: 3,
+    base_delay: Duration::from_millis(500),
+};
+
+/// Runs `op`, described by `what` in logs, retrying it with exponential
+/// backoff and jitter while it fails with a retryable error.
+pub(crate) fn with_backoff<T>(what: &str, op: impl FnMut() -> Result<T>) -> Result<T> {
+    retry(what, &DEFAULT_POLICY, thread::sleep, op)
+}
+
+/// Runs `op` under `policy`, calling `sleep` to wait between attempts.
+///
+/// Errors for which [`is_retryable`] is false are returned immediately,
+/// as is the error of the last attempt.
+pub(crate) fn retry<T>(
+    what: &str,
+    policy: &RetryPolicy,
+    mut sleep: impl FnMut(Duration),
+    mut op: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt >= policy.attempts || !is_retryable(&err) => return Err(err),
+            Err(err) => {
+                let delay = policy.base_delay * 2u32.pow(attempt - 1);
+                let delay = delay + jitter(delay / 2);
+                log(format!(
+                    "{what} failed (attempt {attempt} of {}), retrying in {}ms: {err}",
+                    policy.attempts,
+                    delay.as_millis()
+                ));
+                sleep(delay);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Returns whether `err` may go away by trying again, as network errors
+/// do, unlike missing releases or assets.
+fn is_retryable(err: &str) -> bool {
+    let err = err.to_ascii_lowercase();
+    ![
+        "404",
+        "not found",
+        "no asset",
+        "401",
+        "403",
+        "checksum",
+        "signature",
+        "rate limit",
+    ]
+    .iter()
+    .any(|permanent| err.contains(permanent))
+}
+
+/// Returns a pseudo-random duration below `max`, to keep retries from
+/// several servers apart.
+fn jitter(max: Duration) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.subsec_nanos());
+    match max.as_millis() {
+        0 => Duration::ZERO,
+        max => Duration::from_millis(u64::from(nanos) % max as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        attempts: 3,
+        base_delay: Duration::from_millis(100),
+    };
+
+    /// Runs [`retry`] on `results`, returning its result, how many
+    /// attempts it made, and the delays it slept for.
+    fn run(results: &[Result<u32, &str>]) -> (Result<u32>, usize, Vec<Duration>) {
+        let attempts = RefCell::new(0);
+        let mut delays = vec![];
+        let result = retry(
+            "testing",
+            &POLICY,
+            |delay| delays.push(delay),
+            || {
+                let mut attempts = attempts.borrow_mut();
+                *attempts += 1;
+                results[*attempts - 1].map_err(String::from)
+            },
+        );
+        (result, attempts.into_inner(), delays)
+    }
+
+    #[test]
+    fn retries_transient_errors_with_backoff() {
+        let (result, attempts, delays) = run(&[Err("connection reset"), Err("timed out"), Ok(7)]);
+        assert_eq!(result, Ok(7));
+        assert_eq!(attempts, 3);
+        assert_eq!(delays.len(), 2);
+        // Each delay is the base delay doubled per retry, plus up to half
+        // of it as jitter.
+        assert!((100..150).contains(&delays[0].as_millis()), "{delays:?}");
+        assert!((200..300).contains(&delays[1].as_millis()), "{delays:?}");
+    }
+
+    #[test]
+    fn returns_the_last_error_once_out_of_attempts() {
+        let (result, attempts, delays) = run(&[Err("reset"), Err("reset"), Err("refused")]);
+        assert_eq!(result, Err("refused".into()));
+        assert_eq!(attempts, 3);
+        assert_eq!(delays.len(), 2);
+    }
+
+    #[test]
+    fn returns_permanent_errors_immediately() {
+        for err in [
+            "HTTP 404",
+            "release Not Found",
+            "403 Forbidden",
+            "checksum mismatch",
+        ] {
+            let (result, attempts, delays) = run(&[Err(err), Ok(7)]);
+            assert_eq!(result, Err(err.into()));
+            assert_eq!((attempts, delays.len()), (1, 0), "{err}");
+        }
+        let (result, attempts, _) = run(&[Ok(7)]);
+        assert_eq!((result, attempts), (Ok(7), 1));
+    }
+
+    #[test]
+    fn tells_retryable_errors_apart() {
+        for err in [
+            "connection reset by peer",
+            "operation timed out",
+            "HTTP 502",
+            "dns error",
+        ] {
+            assert!(is_retryable(err), "{err}");
+        }
+        for err in [
+            "HTTP 401",
+            "no asset for Linux",
+            "API rate limit exceeded",
+            "bad signature",
+        ] {
+            assert!(!is_retryable(err), "{err}");
+        }
+    }
+}
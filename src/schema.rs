@@ -0,0 +1,372 @@
+//! The JSON schema of the settings, shown by Zed when configuring the
+//! context server.
+
+use zed_extension_api::serde_json::{self, Value, json};
+
+use crate::tools;
+
+/// Returns the JSON schema of [`GrafanaContextServerSettings`].
+///
+/// This is written by hand, so it must be kept in sync with the settings.
+///
+/// [`GrafanaContextServerSettings`]: crate::settings::GrafanaContextServerSettings
+pub(crate) fn settings_schema() -> Value {
+    let mut properties = settings_properties();
+    let profile_properties = properties.clone();
+    let properties_map = properties
+        .as_object_mut()
+        .expect("settings properties are an object");
+    let profiles = json!({
+        "description": "Named sets of settings, one of which can be selected.",
+        "type": "object",
+        "additionalProperties": {
+            "type": "object",
+            "properties": profile_properties,
+            "additionalProperties": false,
+        },
+    });
+    properties_map.insert("profiles".into(), profiles.clone());
+    properties_map.insert("instances".into(), profiles);
+    properties_map.insert(
+        "config_file".into(),
+        json!({
+            "description": "An absolute path to a JSON file of settings to merge underneath these.",
+            "type": "string",
+        }),
+    );
+    properties_map.insert(
+        "instance_from_env".into(),
+        json!({
+            "description": "An environment variable whose value selects the profile, such as GRAFANA_ENV.",
+            "type": "string",
+        }),
+    );
+    for key in ["profile", "instance"] {
+        properties_map.insert(
+            key.into(),
+            json!({
+                "description": format!("The name of the {key} to apply underneath these settings."),
+                "type": "string",
+            }),
+        );
+    }
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Grafana MCP server settings",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+fn settings_properties() -> Value {
+    let string = |description: &str| json!({ "description": description, "type": "string" });
+    let boolean = |description: &str| json!({ "description": description, "type": "boolean" });
+    let tool_list = |description: &str| {
+        json!({
+            "description": description,
+            "oneOf": [
+                { "type": "array", "items": { "type": "string", "examples": tools::CATEGORIES } },
+                { "type": "string" },
+            ],
+        })
+    };
+
+    let tool_toggles: serde_json::Map<String, Value> = tools::CATEGORIES
+        .iter()
+        .map(|category| ((*category).into(), json!({ "type": "boolean" })))
+        .collect();
+
+    let groups = [
+        json!({
+            "grafana_url": string("The URL of the Grafana instance. Can also be set with GRAFANA_URL."),
+            "org_id": {
+                "description": "The id of the Grafana organization to use. Can also be set with GRAFANA_ORG_ID.",
+                "type": "integer",
+                "minimum": 1,
+            },
+            "cloud_stack": string("The slug of a Grafana Cloud stack, used instead of `grafana_url`."),
+            "cloud_domain": string("The domain of the Grafana Cloud stack. Defaults to grafana.net."),
+            "settings_precedence": {
+                "description": "Whether these settings or environment variables win when both are set.",
+                "enum": ["settings", "env"],
+                "default": "settings",
+            },
+            "strict": boolean("Refuse to start if these settings contain unknown keys."),
+            "strict_secrets": boolean("Refuse to start if a secret is set directly in these settings."),
+            "env_file": string("An absolute path to a dotenv file to load GRAFANA_* variables from."),
+            "inherit_shell_env": boolean("Read GRAFANA_* variables from the login shell."),
+        }),
+        json!({
+            "grafana_service_account_token": string(
+                "The service account token. Can also be set with GRAFANA_SERVICE_ACCOUNT_TOKEN."
+            ),
+            "grafana_api_key": {
+                "description": "Deprecated; use `grafana_service_account_token` instead.",
+                "type": "string",
+                "deprecated": true,
+            },
+            "api_key_file": string("An absolute path to a file containing the service account token."),
+            "api_key_command": {
+                "description": "A command which prints the service account token.",
+                "oneOf": [
+                    { "type": "string" },
+                    { "type": "array", "items": { "type": "string" }, "minItems": 1 },
+                ],
+            },
+            "credential_cache_ttl_seconds": {
+                "description": "How long to reuse the token printed by `api_key_command`, in seconds.",
+                "type": "integer",
+                "minimum": 0,
+                "default": 300,
+            },
+            "credential_source": {
+                "description": "Where to read the service account token from.",
+                "enum": ["settings", "keychain"],
+                "default": "settings",
+            },
+            "keychain_item": string("The service name of the keychain item holding the token."),
+            "keychain_account": string("The account name of the keychain item holding the token."),
+            "grafana_username": string("The username for basic auth. Can also be set with GRAFANA_USERNAME."),
+            "grafana_password": string("The password for basic auth. Can also be set with GRAFANA_PASSWORD."),
+            "grafana_access_token": string(
+                "An OAuth or access policy token. Can also be set with GRAFANA_ACCESS_TOKEN."
+            ),
+            "grafana_id_token": string(
+                "An id token for on-behalf-of authentication. Can also be set with GRAFANA_ID_TOKEN."
+            ),
+            "use_netrc": boolean("Look up basic auth credentials in ~/.netrc."),
+            "allow_anonymous": boolean("Connect to Grafana without any credentials."),
+            "allow_insecure": boolean("Allow credentials to be sent over plain http:// to non-local hosts."),
+        }),
+        json!({
+            "tls_cert_file": string("An absolute path to a client certificate, for mTLS."),
+            "tls_key_file": string("An absolute path to the private key of `tls_cert_file`."),
+            "tls_ca_file": string("An absolute path to a CA bundle to verify Grafana's certificate with."),
+            "tls_skip_verify": boolean("Skip verification of Grafana's TLS certificate."),
+            "tls_skip_verify_any_host": boolean("Allow `tls_skip_verify` for non-local URLs."),
+            "validate_credentials": boolean("Check the URL and credentials before starting the server."),
+            "custom_headers": {
+                "description": "Extra HTTP headers to send with every request to Grafana.",
+                "type": "object",
+                "propertyNames": { "pattern": "^[!#$%&'*+.^_`|~0-9A-Za-z-]+$" },
+                "additionalProperties": { "type": "string" },
+            },
+            "remote_url": string("The URL of a running server serving streamable HTTP, to use instead of running one."),
+            "bridge_command": {
+                "description": "The stdio to HTTP bridge connecting Zed to `remote_url`.",
+                "type": "array",
+                "items": { "type": "string" },
+                "minItems": 1,
+                "default": ["npx", "-y", "mcp-remote"],
+            },
+            "remote_headers": {
+                "description": "HTTP headers to send to `remote_url`.",
+                "type": "object",
+                "propertyNames": { "pattern": "^[!#$%&'*+.^_`|~0-9A-Za-z-]+$" },
+                "additionalProperties": { "type": "string" },
+            },
+            "http_proxy": string("The proxy to use for http:// requests to Grafana."),
+            "https_proxy": string("The proxy to use for https:// requests to Grafana."),
+            "no_proxy": string("Hosts which should be reached without a proxy."),
+            "extra_env": {
+                "description": "Extra environment variables to set for the server.",
+                "type": "object",
+                "propertyNames": { "pattern": "^[A-Za-z_][A-Za-z0-9_]*$" },
+                "additionalProperties": { "type": "string" },
+            },
+            "env_overrides": {
+                "description": "Renames or duplicates the environment variables passed to the server.",
+                "type": "object",
+                "additionalProperties": {
+                    "oneOf": [
+                        { "type": "string" },
+                        { "type": "array", "items": { "type": "string" } },
+                    ],
+                },
+            },
+        }),
+        json!({
+            "enabled_tools": tool_list("The categories of tools to enable."),
+            "disabled_tools": tool_list("The categories of tools to disable."),
+            "tools": {
+                "description": "Whether each category of tools is enabled.",
+                "type": "object",
+                "properties": tool_toggles,
+                "additionalProperties": { "type": "boolean" },
+            },
+            "enabled_tool_names": {
+                "description": "The names of individual tools to enable, or globs such as `list_*`.",
+                "type": "array",
+                "items": { "type": "string", "pattern": "^[A-Za-z0-9_*]+$" },
+            },
+            "disabled_tool_names": {
+                "description": "The names of individual tools to disable, or globs such as `list_*`.",
+                "type": "array",
+                "items": { "type": "string", "pattern": "^[A-Za-z0-9_*]+$" },
+            },
+            "allow_unknown_tools": boolean("Log unknown tool categories instead of rejecting them."),
+            "read_only": boolean("Disable all tools which can modify Grafana."),
+            "datasource_allowlist": {
+                "description": "The UIDs of the only datasources the server may use.",
+                "type": "array",
+                "items": { "type": "string", "minLength": 1 },
+            },
+            "default_prometheus_datasource": {
+                "description": "The UID of the Prometheus datasource to query by default.",
+                "type": "string",
+                "minLength": 1,
+            },
+            "default_loki_datasource": {
+                "description": "The UID of the Loki datasource to query by default.",
+                "type": "string",
+                "minLength": 1,
+            },
+            "max_log_lines": {
+                "description": "The most log lines a single Loki query may return.",
+                "type": "integer",
+                "minimum": 1,
+                "maximum": 100000,
+            },
+            "max_series": {
+                "description": "The most series a single Prometheus query may return.",
+                "type": "integer",
+                "minimum": 1,
+                "maximum": 100000,
+            },
+            "max_query_range": {
+                "description": "The longest time range a single query may cover, such as 6h.",
+                "type": "string",
+                "pattern": "^([0-9]+[hms])+$",
+            },
+            "extra_args": {
+                "description": "Extra arguments to pass to the server.",
+                "type": "array",
+                "items": { "type": "string" },
+            },
+            "timeout_seconds": {
+                "description": "The timeout of requests to Grafana, in seconds.",
+                "type": "integer",
+                "minimum": 1,
+                "maximum": 3600,
+            },
+            "log_file": string("A file to write the server's logs to, as well as Zed's log. Not supported on Windows."),
+            "log_level": {
+                "description": "The log level of the server.",
+                "enum": ["debug", "info", "warn", "error"],
+            },
+            "transport": {
+                "description": "The transport the server serves MCP over. Zed only supports stdio.",
+                "enum": ["stdio"],
+                "default": "stdio",
+            },
+            "debug": {
+                "description": "Deprecated; use `\"log_level\": \"debug\"` instead.",
+                "type": "boolean",
+                "deprecated": true,
+            },
+        }),
+        json!({
+            "runtime": {
+                "description": "How to run the server: a binary, or its container image with docker.",
+                "enum": ["binary", "docker"],
+                "default": "binary",
+            },
+            "docker_image": string("The container image to run with `\"runtime\": \"docker\"`."),
+            "docker_args": {
+                "description": "Extra arguments to pass to `docker run`, such as volume mounts.",
+                "type": "array",
+                "items": { "type": "string" },
+            },
+            "server_version": string(
+                "The release of the server to install, such as v0.2.5, or a range such as 0.2.x. Defaults to the latest."
+            ),
+            "binary_path": string("An existing server binary to use instead of downloading one."),
+            "archive_path": string("A local release archive of the server to install instead of downloading one."),
+            "archive_version": string("The version of the server in `archive_path`, such as 0.2.5."),
+            "dev_path": string("A local checkout of mcp-grafana to build the server from and run."),
+            "dev_binary": string("A binary built from `dev_path` to run while `dev_rebuild` is \"never\"."),
+            "dev_rebuild": {
+                "description": "Whether to build `dev_path` every time the server starts.",
+                "enum": ["always", "never"],
+                "default": "always",
+            },
+            "prefer_system_binary": boolean("Use a server found on PATH before downloading one."),
+            "arch": {
+                "description": "The architecture to install the server for, instead of the detected one.",
+                "enum": ["arm64", "x86_64", "i386"],
+            },
+            "libc": {
+                "description": "The C library of the Linux system, which selects musl builds of the server.",
+                "enum": ["gnu", "musl"],
+            },
+            "prefer_native_arch": {
+                "description": "Install the arm64 server on Apple Silicon when Zed runs under Rosetta.",
+                "type": "boolean",
+                "default": true,
+            },
+            "build_from_source": boolean("Build the server with `go install` instead of downloading it."),
+            "channel": {
+                "description": "Which releases of the server to track.",
+                "enum": ["stable", "prerelease", "nightly"],
+                "default": "stable",
+            },
+            "nightly_repo": string("The GitHub repository to install nightly builds from, such as a fork."),
+            "nightly_tag_pattern": string("The tags of nightly builds, where * matches any run of characters."),
+            "allow_prerelease": boolean("Install pre-releases of the server when they are the newest."),
+            "auto_update": {
+                "description": "Look for new releases of the server when one is already installed.",
+                "type": "boolean",
+                "default": true,
+            },
+            "github_repo": string("The GitHub repository to install the server from, such as a fork."),
+            "github_api_base": string("The API of the GitHub Enterprise server hosting `github_repo`."),
+            "binary_name": string("The name of the server binary in the release archives of `github_repo`."),
+            "asset_name_template": string(
+                "The name of the release asset, with {tag}, {version}, {os}, {arch}, {goos}, {goarch} and {ext} placeholders."
+            ),
+            "github_token": string(
+                "A GitHub token to look up releases with. Can also be set with GITHUB_TOKEN or GH_TOKEN."
+            ),
+            "download_base_url": string("A mirror to download release assets from, as {base}/{tag}/{asset}."),
+            "download_url_template": string(
+                "The URL to download release assets from, with {tag}, {version} and {asset} placeholders."
+            ),
+            "release_check_interval_seconds": {
+                "description": "How long to reuse the last lookup of the latest release, in seconds.",
+                "type": "integer",
+                "minimum": 0,
+                "default": 21600,
+            },
+            "keep_versions": {
+                "description": "How many installed releases of the server to keep, newest first.",
+                "type": "integer",
+                "minimum": 1,
+                "default": 2,
+            },
+            "notify_new_releases": {
+                "description": "Log when a newer release exists while a pin or `auto_update` holds the server back.",
+                "type": "boolean",
+                "default": true,
+            },
+            "storage_dir": string("An absolute path to install releases of the server in, instead of the extension's directory."),
+            "purge_on_next_start": boolean("Remove every installed release once, and download the server again."),
+            "force_reinstall": boolean("Download the selected release again once, even if it is installed."),
+            "strict_latest": boolean("Fail rather than install an earlier release when the latest has no asset for this platform."),
+            "skip_checksum_verification": boolean("Install the server without checking it against checksums.txt."),
+            "verify_signature": boolean("Verify the cosign signature of the release before installing it."),
+            "cosign_public_key": string("A public key file to verify cosign signatures with."),
+            "cosign_identity": string("The certificate identity of keyless cosign signatures."),
+            "cosign_oidc_issuer": string("The OIDC issuer of keyless cosign signatures."),
+        }),
+    ];
+    // Split into groups to stay below the recursion limit of `json!`.
+    let mut properties = serde_json::Map::new();
+    for group in groups {
+        if let Value::Object(group) = group {
+            properties.extend(group);
+        }
+    }
+    Value::Object(properties)
+}
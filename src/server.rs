@@ -0,0 +1,112 @@
+//! Inspection of the installed Grafana MCP server binary.
+
+use zed_extension_api::Result;
+
+use crate::subprocess;
+
+/// How long the server may take to print its usage.
+const HELP_TIMEOUT_SECS: u64 = 10;
+
+/// How long the server's container image may take to print its usage,
+/// which includes pulling the image.
+const DOCKER_HELP_TIMEOUT_SECS: u64 = 300;
+
+/// The installed Grafana MCP server, whose usage is read at most once.
+pub(crate) struct Server {
+    /// The command printing the server's usage.
+    help_argv: Vec<String>,
+    help_timeout_secs: u64,
+    /// Describes the server in error messages.
+    description: String,
+    help: Option<String>,
+}
+
+impl Server {
+    pub(crate) fn new(binary_path: &str) -> Self {
+        Self {
+            help_argv: vec![binary_path.into(), "--help".into()],
+            help_timeout_secs: HELP_TIMEOUT_SECS,
+            description: format!("the Grafana MCP server at '{binary_path}'"),
+            help: None,
+        }
+    }
+
+    /// Returns the server in the container `image`, run with `docker`, whose
+    /// usage is `help` if it has already been read.
+    pub(crate) fn docker(docker: &str, image: &str, help: Option<String>) -> Self {
+        Self {
+            help_argv: vec![
+                docker.into(),
+                "run".into(),
+                "--rm".into(),
+                image.into(),
+                "--help".into(),
+            ],
+            help_timeout_secs: DOCKER_HELP_TIMEOUT_SECS,
+            description: format!("the Grafana MCP server image '{image}'"),
+            help,
+        }
+    }
+
+    /// Describes the server in error messages, such as "the Grafana MCP
+    /// server at '/usr/bin/mcp-grafana'".
+    pub(crate) fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Returns the server's `--help` output, reading it on first use.
+    pub(crate) fn help(&mut self) -> Result<&str> {
+        if self.help.is_none() {
+            self.help = Some(read_help(self.help_argv.clone(), self.help_timeout_secs)?);
+        }
+        Ok(self.help.as_deref().unwrap_or_default())
+    }
+
+    /// Returns whether the server accepts the flag `name` (without leading
+    /// dashes), according to its `--help` output.
+    pub(crate) fn supports_flag(&mut self, name: &str) -> Result<bool> {
+        let help = self.help()?;
+        // Go's flag package writes flags as `-name`; accept `--name` too.
+        Ok(help
+            .split(|c: char| c.is_whitespace() || c == '=' || c == ',')
+            .any(|word| word.starts_with('-') && word.trim_start_matches('-') == name))
+    }
+
+    /// Returns an error saying that `setting` can't be used unless the
+    /// server accepts the flag `name`.
+    pub(crate) fn require_flag(&mut self, setting: &str, name: &str) -> Result<()> {
+        if self.supports_flag(name)? {
+            return Ok(());
+        }
+        Err(format!(
+            "`{setting}` is set, but {} has no `--{name}` flag; remove the setting or update \
+             the server",
+            self.description
+        ))
+    }
+}
+
+/// Returns the usage printed by `argv`.
+fn read_help(argv: Vec<String>, timeout_secs: u64) -> Result<String> {
+    let output = subprocess::run("Grafana MCP server", argv, timeout_secs)?;
+    // Go's flag package prints usage to stderr.
+    Ok(format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_image_help_is_not_read_again() {
+        let help = "Usage of mcp-grafana:\n  -disable-write\n  -t string\n".to_string();
+        let mut server = Server::docker("docker", "mcp/grafana:0.2.5", Some(help));
+        assert_eq!(server.supports_flag("disable-write"), Ok(true));
+        assert_eq!(server.supports_flag("t"), Ok(true));
+        assert_eq!(server.supports_flag("timeout"), Ok(false));
+    }
+}
@@ -0,0 +1,1517 @@
+//! The settings of the Grafana context server.
+
+use std::{collections::BTreeMap, fs};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, Visitor},
+};
+use zed_extension_api::{
+    ContextServerId, Project, Result, serde_json, settings::ContextServerSettings,
+};
+
+use crate::{
+    binary::{Arch, Channel, Libc},
+    credentials::{self, CredentialSource, Secret, TokenCommand},
+    dev::Rebuild,
+    docker::Runtime,
+    interpolate, log, paths,
+    precedence::Precedence,
+    suggest, tools,
+};
+
+/// The new name or names of an environment variable in `env_overrides`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum EnvOverride {
+    Rename(String),
+    Duplicate(Vec<String>),
+}
+
+impl EnvOverride {
+    /// Returns the names the variable should be exported under.
+    pub(crate) fn names(&self) -> &[String] {
+        match self {
+            Self::Rename(name) => std::slice::from_ref(name),
+            Self::Duplicate(names) => names,
+        }
+    }
+}
+
+/// The log level of the Grafana MCP server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Returns the value of the server's `--log-level` flag.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// The transport the Grafana MCP server serves MCP over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Transport {
+    #[default]
+    Stdio,
+    Sse,
+    StreamableHttp,
+}
+
+impl Transport {
+    /// Returns the value of the server's `-t` flag.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Stdio => "stdio",
+            Self::Sse => "sse",
+            Self::StreamableHttp => "streamable-http",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GrafanaContextServerSettings {
+    /// The URL of the Grafana instance.
+    ///
+    /// Note this is marked as optional because it may come from the
+    /// `GRAFANA_URL` environment variable instead.
+    #[serde(default)]
+    pub(crate) grafana_url: Option<String>,
+
+    /// The id of the Grafana organization to use.
+    ///
+    /// Needed when the organization isn't the default one of the
+    /// credentials. It can also be set using the `GRAFANA_ORG_ID`
+    /// environment variable. Must be positive.
+    #[serde(default)]
+    pub(crate) org_id: Option<u64>,
+
+    /// The slug of a Grafana Cloud stack, such as `acme-prod`.
+    ///
+    /// When set, the URL is derived as `https://{cloud_stack}.grafana.net`.
+    /// Cannot be combined with `grafana_url`.
+    #[serde(default)]
+    pub(crate) cloud_stack: Option<String>,
+
+    /// The domain of the Grafana Cloud stack in `cloud_stack`.
+    ///
+    /// Only needed for stacks outside `grafana.net`, such as regional or
+    /// government endpoints. Defaults to `"grafana.net"`.
+    #[serde(default)]
+    pub(crate) cloud_domain: Option<String>,
+
+    /// Whether values in these settings or environment variables win when
+    /// both are set.
+    ///
+    /// This applies consistently to the URL and all credentials. Defaults
+    /// to `"settings"`; set to `"env"` to let environment variables
+    /// override the settings.
+    #[serde(default)]
+    pub(crate) settings_precedence: Precedence,
+
+    /// Refuse to start if these settings contain unknown keys.
+    ///
+    /// Unknown keys are always logged, with the closest known key, but are
+    /// otherwise ignored so that settings can carry keys for other tools.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) strict: bool,
+
+    /// Refuse to start if a secret is set directly in these settings.
+    ///
+    /// Intended for settings committed to a repository, where a pasted
+    /// token would be leaked. Tokens should then come from environment
+    /// variables, `api_key_file`, `api_key_command` or the keychain
+    /// instead. Can also be enabled with the
+    /// `ZED_MCP_GRAFANA_STRICT_SECRETS=1` environment variable.
+    ///
+    /// Note that Zed merges user and project settings before handing them
+    /// to the extension, so this applies to secrets in either.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) strict_secrets: bool,
+
+    /// A dotenv-format file to load `GRAFANA_*` variables from.
+    ///
+    /// Variables in the file are treated like environment variables, but
+    /// variables set in the extension's own environment take precedence.
+    /// Any other `GRAFANA_*` variables in the file are passed on to the
+    /// server as-is. Must be an absolute path.
+    #[serde(default)]
+    pub(crate) env_file: Option<String>,
+
+    /// Read `GRAFANA_*` variables from the user's login shell.
+    ///
+    /// This lets variables exported from e.g. `~/.zprofile` be used even
+    /// when Zed wasn't started from a terminal. They take precedence over
+    /// the `env_file`, but not over the extension's own environment, and
+    /// like those in the `env_file` any other `GRAFANA_*` variables are
+    /// passed on to the server as-is. Not supported on Windows.
+    ///
+    /// Off by default, since it runs the login shell on every start.
+    #[serde(default)]
+    pub(crate) inherit_shell_env: bool,
+
+    /// The service account token of the Grafana instance.
+    ///
+    /// This is optional if the Grafana instance is accessible without
+    /// authentication. It can also be set using the
+    /// `GRAFANA_SERVICE_ACCOUNT_TOKEN` environment variable.
+    #[serde(default)]
+    pub(crate) grafana_service_account_token: Option<Secret>,
+
+    /// A file containing the service account token of the Grafana instance.
+    ///
+    /// The file is re-read every time the server is started, so a rotated
+    /// token is picked up by restarting the context server. Trailing
+    /// whitespace is trimmed. Takes precedence over
+    /// `grafana_service_account_token` and `grafana_api_key`.
+    #[serde(default)]
+    pub(crate) api_key_file: Option<String>,
+
+    /// A command which prints the service account token to stdout.
+    ///
+    /// Either a string, which is run using the system shell, or an array
+    /// of program and arguments, e.g. `["op", "read", "op://vault/grafana/token"]`.
+    /// The command is run every time the server is started and its trimmed
+    /// stdout is used as the token. Cannot be combined with `api_key_file`.
+    #[serde(default)]
+    pub(crate) api_key_command: Option<TokenCommand>,
+
+    /// How long to reuse the token printed by `api_key_command`, in
+    /// seconds.
+    ///
+    /// The token is only kept in memory, and is fetched again when the
+    /// command changes. Set to 0 to run the command on every start.
+    ///
+    /// Defaults to 300.
+    #[serde(default = "default_credential_cache_ttl_seconds")]
+    pub(crate) credential_cache_ttl_seconds: u64,
+
+    /// Where to read the service account token from.
+    ///
+    /// Set to `"keychain"` to read it from the macOS Keychain, the Secret
+    /// Service on Linux or the Windows Credential Manager, using
+    /// `keychain_item` and `keychain_account`. Defaults to `"settings"`.
+    #[serde(default)]
+    pub(crate) credential_source: CredentialSource,
+
+    /// The service name of the keychain item holding the token.
+    ///
+    /// Defaults to `"zed-mcp-grafana"`.
+    #[serde(default)]
+    pub(crate) keychain_item: Option<String>,
+
+    /// The account name of the keychain item holding the token.
+    ///
+    /// Defaults to `None`, which matches any account.
+    #[serde(default)]
+    pub(crate) keychain_account: Option<String>,
+
+    /// The username to use for basic authentication.
+    ///
+    /// This is an alternative to a service account token for Grafana instances
+    /// which only allow basic auth. It can also be set using the
+    /// `GRAFANA_USERNAME` environment variable.
+    #[serde(default)]
+    pub(crate) grafana_username: Option<String>,
+
+    /// The password to use for basic authentication.
+    ///
+    /// Must be set together with `grafana_username`. It can also be set
+    /// using the `GRAFANA_PASSWORD` environment variable.
+    #[serde(default)]
+    pub(crate) grafana_password: Option<Secret>,
+
+    /// A Grafana Cloud access policy token.
+    ///
+    /// This is an alternative to a service account token for Grafana Cloud
+    /// stacks using access policies. It can also be set using the
+    /// `GRAFANA_ACCESS_TOKEN` environment variable.
+    #[serde(default)]
+    pub(crate) grafana_access_token: Option<Secret>,
+
+    /// An id token used for on-behalf-of authentication to Grafana Cloud.
+    ///
+    /// Only valid together with `grafana_access_token`. It can also be set
+    /// using the `GRAFANA_ID_TOKEN` environment variable.
+    #[serde(default)]
+    pub(crate) grafana_id_token: Option<Secret>,
+
+    /// Look up basic auth credentials in `~/.netrc` when no others are
+    /// configured.
+    ///
+    /// The entry for the host of the Grafana URL is used, falling back to
+    /// the `default` entry. On Windows `%USERPROFILE%\_netrc` is used
+    /// instead, and the `NETRC` environment variable overrides the path
+    /// on all platforms.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) use_netrc: bool,
+
+    /// Connect to Grafana without any credentials.
+    ///
+    /// This is for Grafana instances with anonymous access enabled. When
+    /// set, no credentials are sent even if some are configured; when
+    /// unset, it is an error for no credentials to be configured.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) allow_anonymous: bool,
+
+    /// Allow credentials to be sent over plain `http://` to hosts which
+    /// don't look local.
+    ///
+    /// Local URLs (such as `localhost` or private network addresses) are
+    /// always allowed.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) allow_insecure: bool,
+
+    /// A client certificate to present to Grafana, for mTLS.
+    ///
+    /// Must be an absolute path, and must be set together with
+    /// `tls_key_file`.
+    #[serde(default)]
+    pub(crate) tls_cert_file: Option<String>,
+
+    /// The private key of the client certificate in `tls_cert_file`.
+    ///
+    /// Must be an absolute path, and must be set together with
+    /// `tls_cert_file`.
+    #[serde(default)]
+    pub(crate) tls_key_file: Option<String>,
+
+    /// A CA bundle used to verify Grafana's TLS certificate.
+    ///
+    /// Must be an absolute path. Cannot be combined with `tls_skip_verify`.
+    #[serde(default)]
+    pub(crate) tls_ca_file: Option<String>,
+
+    /// Skip verification of Grafana's TLS certificate.
+    ///
+    /// This is only allowed for local URLs (such as `localhost` or private
+    /// network addresses) unless `tls_skip_verify_any_host` is also set.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) tls_skip_verify: bool,
+
+    /// Allow `tls_skip_verify` for URLs which don't look local.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) tls_skip_verify_any_host: bool,
+
+    /// Check the URL and credentials against the Grafana API before
+    /// starting the server.
+    ///
+    /// This requests `/api/user` (or `/api/health` when connecting
+    /// anonymously), and fails with an error saying whether the URL, TLS
+    /// or the credentials look to be at fault. Leave this off for
+    /// air-gapped or slow environments.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) validate_credentials: bool,
+
+    /// Extra HTTP headers to send with every request to Grafana, such as
+    /// those required by Cloudflare Access.
+    ///
+    /// Passed to the server as JSON in `GRAFANA_EXTRA_HEADERS`. Values are
+    /// treated as secrets and masked in logs. Header names are
+    /// case-insensitive, so names differing only by case are rejected.
+    #[serde(default)]
+    pub(crate) custom_headers: BTreeMap<String, Secret>,
+
+    /// The URL of an already running Grafana MCP server serving streamable
+    /// HTTP, such as `https://mcp.example.com/mcp`, to use instead of
+    /// running one.
+    ///
+    /// Nothing is downloaded, and Grafana's URL and credentials are left to
+    /// that server; Zed talks to it through `bridge_command`.
+    #[serde(default)]
+    pub(crate) remote_url: Option<String>,
+
+    /// The program and arguments of the stdio to HTTP bridge which
+    /// connects Zed to `remote_url`, such as `["mcp-remote"]`.
+    ///
+    /// The bridge is given the URL, then `--header Name:value` for each of
+    /// `remote_headers`. Defaults to `["npx", "-y", "mcp-remote"]`.
+    #[serde(default)]
+    pub(crate) bridge_command: Option<Vec<String>>,
+
+    /// HTTP headers to send to `remote_url`, such as its `Authorization`.
+    ///
+    /// Values are treated as secrets. Names differing only by case are
+    /// rejected.
+    #[serde(default)]
+    pub(crate) remote_headers: BTreeMap<String, Secret>,
+
+    /// The proxy to use for `http://` requests to Grafana.
+    ///
+    /// Exported to the server as both `http_proxy` and `HTTP_PROXY`. When
+    /// unset, either of those in the extension's environment is used.
+    #[serde(default)]
+    pub(crate) http_proxy: Option<String>,
+
+    /// The proxy to use for `https://` requests to Grafana.
+    ///
+    /// Exported to the server as both `https_proxy` and `HTTPS_PROXY`.
+    /// When unset, either of those in the extension's environment is used.
+    #[serde(default)]
+    pub(crate) https_proxy: Option<String>,
+
+    /// Hosts which should be reached without a proxy.
+    ///
+    /// Exported to the server as both `no_proxy` and `NO_PROXY`. When
+    /// unset, either of those in the extension's environment is used.
+    #[serde(default)]
+    pub(crate) no_proxy: Option<String>,
+
+    /// Extra environment variables to set for the server.
+    ///
+    /// These can't replace variables the extension sets itself, such as
+    /// `GRAFANA_URL` or the credentials. Values of variables whose names
+    /// contain `TOKEN`, `PASSWORD`, `SECRET` or `KEY` are masked in logs.
+    #[serde(default)]
+    pub(crate) extra_env: BTreeMap<String, String>,
+
+    /// Renames or duplicates the environment variables passed to the
+    /// server.
+    ///
+    /// Maps the name of a variable the extension would set (such as
+    /// `GRAFANA_SERVICE_ACCOUNT_TOKEN`) to either a new name, or a list of
+    /// names to set it under instead. Include the original name in the
+    /// list to keep it as well. Variables which aren't set are ignored.
+    #[serde(default)]
+    pub(crate) env_overrides: BTreeMap<String, EnvOverride>,
+
+    /// Enabled categories of tools.
+    ///
+    /// Either an array or a comma-separated string, as in the server's
+    /// `--enabled-tools` flag. Entries prefixed with `!`, such as
+    /// `["!admin", "!oncall"]`, instead enable every category except those,
+    /// and can't be mixed with plain entries.
+    ///
+    /// See the [README of the Grafana MCP server][readme] to see the list
+    /// of available categories.
+    ///
+    /// Defaults to `None`, which means all tools are enabled.
+    ///
+    /// [readme]: https://github.com/grafana/mcp-grafana
+    #[serde(default, deserialize_with = "tools::deserialize_list")]
+    pub(crate) enabled_tools: Option<Vec<String>>,
+
+    /// Disabled categories of tools.
+    ///
+    /// Every other category stays enabled. Cannot contain categories which
+    /// are also in `enabled_tools`.
+    ///
+    /// Defaults to `None`, which means no tools are disabled.
+    #[serde(default, deserialize_with = "tools::deserialize_list")]
+    pub(crate) disabled_tools: Option<Vec<String>>,
+
+    /// Whether each category of tools is enabled, such as
+    /// `{"oncall": false, "sift": false}`.
+    ///
+    /// Categories which aren't listed stay enabled. Cannot be combined
+    /// with `enabled_tools` or `disabled_tools`.
+    #[serde(default)]
+    pub(crate) tools: Option<BTreeMap<String, bool>>,
+
+    /// Names of individual tools to enable, such as `query_prometheus`,
+    /// or globs such as `list_*`.
+    ///
+    /// These refine the categories: only the named tools of the enabled
+    /// categories are exposed, and naming a tool whose category is
+    /// disabled is an error. Unknown names are logged rather than
+    /// rejected.
+    ///
+    /// Defaults to empty, which means every tool of the enabled categories.
+    #[serde(default)]
+    pub(crate) enabled_tool_names: Vec<String>,
+
+    /// Names of individual tools to disable, or globs such as `list_*`.
+    ///
+    /// Cannot contain tools which are also in `enabled_tool_names`.
+    #[serde(default)]
+    pub(crate) disabled_tool_names: Vec<String>,
+
+    /// Allow tool categories which the extension doesn't know about.
+    ///
+    /// Unknown categories in `enabled_tools`, `disabled_tools` and `tools`
+    /// are then logged rather than rejected, for use with server versions
+    /// newer than the extension.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) allow_unknown_tools: bool,
+
+    /// Disable all tools which can modify Grafana.
+    ///
+    /// The categories selected by `enabled_tools` and `disabled_tools`
+    /// are kept, but write tools within them are dropped. The server is
+    /// not started if it doesn't support this.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) read_only: bool,
+
+    /// The UIDs of the only datasources the server may use.
+    ///
+    /// Passed to the server's `--datasource-allowlist` flag. The server is
+    /// not started if it doesn't support this. An empty list means all
+    /// datasources may be used.
+    #[serde(default)]
+    pub(crate) datasource_allowlist: Vec<String>,
+
+    /// The UID of the Prometheus datasource to query by default.
+    ///
+    /// Passed to the server's `--default-prometheus-datasource` flag.
+    #[serde(default)]
+    pub(crate) default_prometheus_datasource: Option<String>,
+
+    /// The UID of the Loki datasource to query by default.
+    ///
+    /// Passed to the server's `--default-loki-datasource` flag.
+    #[serde(default)]
+    pub(crate) default_loki_datasource: Option<String>,
+
+    /// The most log lines a single Loki query may return.
+    ///
+    /// Must be between 1 and 100000. Passed to the server's
+    /// `--max-log-lines` flag.
+    #[serde(default)]
+    pub(crate) max_log_lines: Option<u64>,
+
+    /// The most series a single Prometheus query may return.
+    ///
+    /// Must be between 1 and 100000. Passed to the server's `--max-series`
+    /// flag.
+    #[serde(default)]
+    pub(crate) max_series: Option<u64>,
+
+    /// The longest time range a single query may cover, such as `"6h"`.
+    ///
+    /// Passed to the server's `--max-query-range` flag.
+    #[serde(default)]
+    pub(crate) max_query_range: Option<String>,
+
+    /// Extra arguments to pass to the server, after those generated by the
+    /// extension.
+    ///
+    /// Each entry is passed as a separate argument, without any shell
+    /// quoting. Flags which the extension manages itself, such as
+    /// `--enabled-tools` or `--debug`, are rejected.
+    #[serde(default)]
+    pub(crate) extra_args: Vec<String>,
+
+    /// The timeout of requests from the server to Grafana, in seconds.
+    ///
+    /// Must be between 1 and 3600. Defaults to `None`, which leaves the
+    /// server's default timeout.
+    #[serde(default)]
+    pub(crate) timeout_seconds: Option<u64>,
+
+    /// A file to write the server's logs to, as well as Zed's log.
+    ///
+    /// Must be an absolute path, or start with `~/`. Missing parent
+    /// directories are created, and the file is truncated every time the
+    /// server starts. Setting it on Windows is an error.
+    #[serde(default)]
+    pub(crate) log_file: Option<String>,
+
+    /// The log level of the Grafana MCP server.
+    ///
+    /// One of `"debug"`, `"info"`, `"warn"` or `"error"`. At `"debug"`
+    /// the server's debug flag is also set, which causes requests to and
+    /// responses from the Grafana instance to be logged, and the extension
+    /// logs what it resolves on every start, such as the command used to
+    /// start the server with secrets masked. The legacy `"debug": true` is
+    /// an alias for `"debug"`.
+    ///
+    /// Defaults to `None`, which leaves the server's default level.
+    #[serde(default)]
+    pub(crate) log_level: Option<LogLevel>,
+
+    /// The transport the server serves MCP over, passed to its `-t` flag.
+    ///
+    /// Zed talks to context servers over stdin and stdout, so only
+    /// `"stdio"` works, and anything else is refused when the settings are
+    /// parsed. It is always passed even though it is the default, so that
+    /// a release changing the default doesn't break the server. Defaults
+    /// to `"stdio"`.
+    #[serde(default)]
+    pub(crate) transport: Transport,
+
+    /// How to run the Grafana MCP server: `"binary"` to download or use a
+    /// binary, or `"docker"` to run its container image with `docker run`.
+    ///
+    /// Defaults to `"binary"`.
+    #[serde(default)]
+    pub(crate) runtime: Runtime,
+
+    /// The container image to run with `"runtime": "docker"`.
+    ///
+    /// Defaults to the official `mcp/grafana` image of the release pinned by
+    /// `server_version`, or of the latest release.
+    #[serde(default)]
+    pub(crate) docker_image: Option<String>,
+
+    /// Extra arguments to pass to `docker run`, such as `--network=host` or
+    /// volume mounts for TLS certificates.
+    #[serde(default)]
+    pub(crate) docker_args: Vec<String>,
+
+    /// The release of the Grafana MCP server to install, such as `v0.2.5`,
+    /// or a semver range such as `0.2.x` or `>=0.2, <0.3`.
+    ///
+    /// A pinned release is reused without contacting GitHub once it is
+    /// installed, and is never cleaned up. A range selects the newest
+    /// release satisfying it, excluding pre-releases unless the range
+    /// names one.
+    ///
+    /// Defaults to `None`, which means the latest release.
+    #[serde(default)]
+    pub(crate) server_version: Option<String>,
+
+    /// An existing Grafana MCP server binary to use instead of downloading
+    /// one, such as `/opt/grafana/bin/mcp-grafana`.
+    ///
+    /// Must be an absolute path, or start with `~/`.
+    #[serde(default)]
+    pub(crate) binary_path: Option<String>,
+
+    /// A local release archive of the Grafana MCP server to install instead
+    /// of downloading one, such as `~/Downloads/mcp-grafana_Linux_x86_64.tar.gz`.
+    ///
+    /// Must be an absolute path, or start with `~/`.
+    #[serde(default)]
+    pub(crate) archive_path: Option<String>,
+
+    /// The version of the server in `archive_path`, such as `0.2.5`.
+    ///
+    /// Defaults to the version in the archive's file name, if it has one.
+    #[serde(default)]
+    pub(crate) archive_version: Option<String>,
+
+    /// A local checkout of mcp-grafana to build the server from with
+    /// `go build` and run, instead of downloading a release, such as
+    /// `~/src/mcp-grafana`.
+    ///
+    /// Must be an absolute path, or start with `~/`.
+    #[serde(default)]
+    pub(crate) dev_path: Option<String>,
+
+    /// A binary built from `dev_path` to run instead of building it, while
+    /// `dev_rebuild` is `"never"`.
+    ///
+    /// Must be an absolute path, or start with `~/`.
+    #[serde(default)]
+    pub(crate) dev_binary: Option<String>,
+
+    /// When to build `dev_path`: `"always"`, every time the server starts,
+    /// or `"never"`, which runs `dev_binary`, or the last build if it isn't
+    /// set.
+    ///
+    /// Defaults to `"always"`.
+    #[serde(default)]
+    pub(crate) dev_rebuild: Rebuild,
+
+    /// Use a Grafana MCP server found on `PATH`, such as one installed with
+    /// `go install` or Homebrew, before downloading one.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) prefer_system_binary: bool,
+
+    /// The architecture to install the Grafana MCP server for, one of
+    /// `"arm64"`, `"x86_64"` or `"i386"`.
+    ///
+    /// Defaults to `None`, which means the architecture Zed reports.
+    #[serde(default)]
+    pub(crate) arch: Option<Arch>,
+
+    /// The C library of the Linux system, `"gnu"` or `"musl"`, which
+    /// decides whether a musl build of the server is installed if the
+    /// release has one.
+    ///
+    /// Defaults to `None`, which means musl if its dynamic linker exists.
+    #[serde(default)]
+    pub(crate) libc: Option<Libc>,
+
+    /// Install the native arm64 server on Apple Silicon Macs when Zed runs
+    /// as x86_64 under Rosetta, rather than the x86_64 one.
+    ///
+    /// Defaults to true.
+    #[serde(default = "default_true")]
+    pub(crate) prefer_native_arch: bool,
+
+    /// Build the Grafana MCP server from source with `go install` instead of
+    /// downloading a release asset.
+    ///
+    /// Releases without an asset for the platform are built this way too if
+    /// `go` is on `PATH`. Defaults to false.
+    #[serde(default)]
+    pub(crate) build_from_source: bool,
+
+    /// Which releases of the Grafana MCP server to track: `"stable"`,
+    /// `"prerelease"`, which is the same as `allow_prerelease`, or
+    /// `"nightly"`, for the builds published as releases of `nightly_repo`
+    /// tagged like `nightly_tag_pattern`.
+    ///
+    /// Nightly builds are installed apart from releases, and looked for at
+    /// least every hour. Defaults to `"stable"`.
+    #[serde(default)]
+    pub(crate) channel: Channel,
+
+    /// The GitHub repository to install nightly builds from, such as a
+    /// fork of the server.
+    ///
+    /// Defaults to `grafana/mcp-grafana`.
+    #[serde(default)]
+    pub(crate) nightly_repo: Option<String>,
+
+    /// The tags of the nightly builds of `nightly_repo`, in which `*`
+    /// matches any run of characters.
+    ///
+    /// Defaults to `nightly*`.
+    #[serde(default)]
+    pub(crate) nightly_tag_pattern: Option<String>,
+
+    /// Install pre-releases, such as `v1.0.0-rc.1`, when they are the
+    /// latest release or the newest one in the `server_version` range.
+    ///
+    /// Pinning a pre-release tag works without this. Defaults to false.
+    #[serde(default)]
+    pub(crate) allow_prerelease: bool,
+
+    /// Look for new releases of the server when one is already installed.
+    ///
+    /// When false, the newest installed release is used without contacting
+    /// GitHub, which is only consulted if none is installed. Defaults to
+    /// true.
+    #[serde(default = "default_true")]
+    pub(crate) auto_update: bool,
+
+    /// The GitHub repository to install the Grafana MCP server from, such as
+    /// a fork.
+    ///
+    /// Defaults to `grafana/mcp-grafana`.
+    #[serde(default)]
+    pub(crate) github_repo: Option<String>,
+
+    /// The API of the GitHub Enterprise server hosting `github_repo`, such
+    /// as `https://github.example.com/api/v3`.
+    ///
+    /// Defaults to `None`, which means github.com.
+    #[serde(default)]
+    pub(crate) github_api_base: Option<String>,
+
+    /// The name of the server binary in the release archives of
+    /// `github_repo`, without `.exe`.
+    ///
+    /// Defaults to `mcp-grafana`.
+    #[serde(default)]
+    pub(crate) binary_name: Option<String>,
+
+    /// The name of the release asset for the platform, such as
+    /// `mcp-grafana-{version}-{goos}-{goarch}.tgz`, for releases not named
+    /// like the server's.
+    ///
+    /// `{tag}`, `{version}` (the tag without its `v`), `{os}` and `{arch}`
+    /// (as in `Darwin` and `x86_64`), `{goos}` and `{goarch}` (as in
+    /// `darwin` and `amd64`) and `{ext}` (`tar.gz`, or `zip` on Windows)
+    /// are replaced. Defaults to `None`, which means the server's names.
+    #[serde(default)]
+    pub(crate) asset_name_template: Option<String>,
+
+    /// A GitHub token to look up releases with, which raises GitHub's rate
+    /// limit.
+    ///
+    /// Defaults to the `GITHUB_TOKEN` or `GH_TOKEN` environment variable.
+    /// Downloads themselves don't use it, since the extension API can't
+    /// send headers with them.
+    #[serde(default)]
+    pub(crate) github_token: Option<Secret>,
+
+    /// A mirror to download release assets from instead of GitHub, such as
+    /// `https://artifactory.example.com/mcp-grafana`.
+    ///
+    /// Assets are downloaded from `{base}/{tag}/{asset}`; releases are
+    /// still looked up on GitHub.
+    #[serde(default)]
+    pub(crate) download_base_url: Option<String>,
+
+    /// The URL to download release assets from, for mirrors with other
+    /// layouts than `download_base_url`.
+    ///
+    /// `{tag}`, `{version}` (the tag without its `v`) and `{asset}` are
+    /// replaced with those of the asset.
+    #[serde(default)]
+    pub(crate) download_url_template: Option<String>,
+
+    /// How long to reuse the last lookup of the latest release, in seconds,
+    /// before asking GitHub again.
+    ///
+    /// The lookup is persisted between sessions. Defaults to 21600, which
+    /// is 6 hours; 0 looks up the latest release every time.
+    #[serde(default = "default_release_check_interval_seconds")]
+    pub(crate) release_check_interval_seconds: u64,
+
+    /// How many installed releases of the server to keep, newest first,
+    /// when installing another.
+    ///
+    /// The release in use and pinned releases are always kept. Defaults
+    /// to 2, which keeps the previous release for rolling back.
+    #[serde(default = "default_keep_versions")]
+    pub(crate) keep_versions: usize,
+
+    /// The absolute path of the directory to install releases of the
+    /// server in, instead of the extension's working directory.
+    ///
+    /// A leading `~/` is expanded to the home directory. Releases already
+    /// installed elsewhere are downloaded again.
+    #[serde(default)]
+    pub(crate) storage_dir: Option<String>,
+
+    /// Remove every installed release of the server the next time it
+    /// starts, and download it again from scratch.
+    ///
+    /// This happens once; set it back to false before using it again.
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) purge_on_next_start: bool,
+
+    /// Download the release the extension selects again the next time the
+    /// server starts, even if it is installed, replacing its directory once
+    /// the download is verified.
+    ///
+    /// This happens once; set it back to false before using it again.
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) force_reinstall: bool,
+
+    /// Log when a release newer than the one in use exists, while
+    /// `server_version` or `auto_update` keeps the server from upgrading.
+    ///
+    /// This is logged once per session. Defaults to true.
+    #[serde(default = "default_true")]
+    pub(crate) notify_new_releases: bool,
+
+    /// Fail rather than install an earlier release when the latest release
+    /// has no asset for this platform.
+    ///
+    /// Defaults to false, which means the newest of the 5 releases before
+    /// the latest with an asset is installed instead.
+    #[serde(default)]
+    pub(crate) strict_latest: bool,
+
+    /// Install the server without checking the downloaded archive against
+    /// the SHA-256 in the release's `checksums.txt`, for forks which don't
+    /// publish checksums.
+    ///
+    /// Defaults to false.
+    #[serde(default)]
+    pub(crate) skip_checksum_verification: bool,
+
+    /// Verify the cosign signature of the release's checksums before
+    /// installing the server, using `cosign` on `PATH`.
+    ///
+    /// Requires either `cosign_public_key` or `cosign_identity`. Defaults
+    /// to false.
+    #[serde(default)]
+    pub(crate) verify_signature: bool,
+
+    /// A public key file to verify signatures with.
+    #[serde(default)]
+    pub(crate) cosign_public_key: Option<String>,
+
+    /// The certificate identity of keyless signatures, such as the URL of
+    /// the release workflow.
+    #[serde(default)]
+    pub(crate) cosign_identity: Option<String>,
+
+    /// The OIDC issuer of keyless signatures.
+    ///
+    /// Defaults to GitHub Actions' issuer.
+    #[serde(default)]
+    pub(crate) cosign_oidc_issuer: Option<String>,
+
+    /// The secrets set directly in the settings rather than through
+    /// `${VAR}`, found before the settings are interpolated; see
+    /// [`credentials::plaintext_secrets`].
+    #[serde(skip)]
+    pub(crate) plaintext_secrets: Vec<String>,
+
+    /// Which profile was selected and how, such as "profile 'prod'"; see
+    /// [`apply_profile`].
+    #[serde(skip)]
+    pub(crate) profile: Option<String>,
+}
+
+fn default_release_check_interval_seconds() -> u64 {
+    6 * 60 * 60
+}
+
+fn default_keep_versions() -> usize {
+    2
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_credential_cache_ttl_seconds() -> u64 {
+    300
+}
+
+/// Returns the raw settings of the context server for `project`.
+///
+/// Settings are looked up in this order: the project's Zed settings, then
+/// the user's Zed settings (which Zed falls back to itself), and if neither
+/// has a settings block an empty one is returned, so that everything can
+/// come from environment variables instead.
+pub(crate) fn load(
+    context_server_id: &ContextServerId,
+    project: &Project,
+) -> Result<serde_json::Value> {
+    let settings = ContextServerSettings::for_project(context_server_id.as_ref(), project)?;
+    Ok(settings
+        .settings
+        .unwrap_or_else(|| serde_json::Value::Object(Default::default())))
+}
+
+/// Parses the raw settings of the context server.
+///
+/// The `config_file`, if any, is merged underneath the settings first; see
+/// [`merge_config_file`]. The profile selected by `profile`, if any, is
+/// applied next; see [`apply_profile`]. Then `${VAR}` references in string
+/// values are replaced with the values of the extension's environment
+/// variables.
+pub(crate) fn parse(value: serde_json::Value) -> Result<GrafanaContextServerSettings> {
+    let lookup = |name: &str| std::env::var(name).ok();
+    let value = merge_config_file(value, &lookup)?;
+    let (value, profile) = apply_profile(value, &lookup)?;
+    let mut value = apply_aliases(value)?;
+    let plaintext_secrets = credentials::plaintext_secrets(&value);
+    interpolate::interpolate(&mut value, &lookup)?;
+    let mut settings: GrafanaContextServerSettings = serde_json::from_value(value.clone())
+        .map_err(|e| {
+            let secrets = credentials::SECRET_SETTINGS
+                .iter()
+                .filter_map(|key| value.get(key)?.as_str());
+            format!(
+                "invalid Grafana settings: {}",
+                credentials::redact(&e.to_string(), secrets)
+            )
+        })?;
+    check_unknown_fields(&value, settings.strict)?;
+    if settings.transport != Transport::Stdio {
+        return Err(format!(
+            "`transport` is \"{}\", but Zed only talks to context servers over stdin and \
+             stdout; remove `transport` or set it to \"stdio\"",
+            settings.transport.as_str()
+        ));
+    }
+    settings.plaintext_secrets = plaintext_secrets;
+    settings.profile = profile;
+    Ok(settings)
+}
+
+/// How to resolve a deprecated setting which is set alongside its
+/// replacement.
+enum AliasConflict {
+    /// Fail unless both have the same value.
+    Error,
+    /// Use the replacement, and log that the deprecated setting is ignored.
+    PreferNew,
+}
+
+/// A deprecated setting which is still accepted under its old name.
+struct Alias {
+    old: &'static str,
+    new: &'static str,
+    /// Converts the old value into the new one, or `None` to drop it.
+    convert: fn(serde_json::Value) -> Result<Option<serde_json::Value>>,
+    conflict: AliasConflict,
+}
+
+/// The deprecated settings, and the settings which replace them.
+const ALIASES: &[Alias] = &[
+    Alias {
+        old: "grafana_api_key",
+        new: "grafana_service_account_token",
+        convert: |value| Ok(Some(value)),
+        conflict: AliasConflict::Error,
+    },
+    Alias {
+        old: "debug",
+        new: "log_level",
+        convert: |value| match value {
+            serde_json::Value::Bool(true) => Ok(Some("debug".into())),
+            serde_json::Value::Bool(false) | serde_json::Value::Null => Ok(None),
+            _ => Err("`debug` must be a boolean".into()),
+        },
+        conflict: AliasConflict::PreferNew,
+    },
+];
+
+/// Moves deprecated settings to the settings which replace them, logging a
+/// notice for each; see [`ALIASES`].
+fn apply_aliases(value: serde_json::Value) -> Result<serde_json::Value> {
+    let serde_json::Value::Object(mut settings) = value else {
+        return Err("invalid Grafana settings: expected an object".into());
+    };
+    for alias in ALIASES {
+        let Some(old) = settings.remove(alias.old) else {
+            continue;
+        };
+        let (old_name, new_name) = (alias.old, alias.new);
+        log(format_args!(
+            "`{old_name}` is deprecated, use `{new_name}` instead"
+        ));
+        let Some(converted) = (alias.convert)(old)? else {
+            continue;
+        };
+        match settings.get(new_name) {
+            None => {
+                settings.insert(new_name.into(), converted);
+            }
+            Some(new) if *new == converted => {}
+            Some(_) => match alias.conflict {
+                AliasConflict::Error => {
+                    return Err(format!(
+                        "both `{old_name}` and `{new_name}` are set to different values; \
+                         remove the deprecated `{old_name}`"
+                    ));
+                }
+                AliasConflict::PreferNew => log(format_args!(
+                    "both `{old_name}` and `{new_name}` are set; ignoring `{old_name}`"
+                )),
+            },
+        }
+    }
+    Ok(settings.into())
+}
+
+/// Logs settings which don't correspond to any field, suggesting the
+/// closest field for each, and rejects them if `strict` is set.
+fn check_unknown_fields(value: &serde_json::Value, strict: bool) -> Result<()> {
+    let Some(settings) = value.as_object() else {
+        return Ok(());
+    };
+    let fields = field_names();
+    let unknown: Vec<String> = settings
+        .keys()
+        .filter(|key| !fields.contains(&key.as_str()))
+        .map(|key| match suggest::closest(key, fields) {
+            Some(field) => format!("`{key}` (did you mean `{field}`?)"),
+            None => format!("`{key}`"),
+        })
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    let message = format!("unknown Grafana settings: {}", unknown.join(", "));
+    if strict {
+        return Err(message);
+    }
+    log(format_args!("ignoring {message}"));
+    Ok(())
+}
+
+/// Returns the names of the fields of [`GrafanaContextServerSettings`].
+///
+/// Serde passes these to the deserializer, so this uses one which only
+/// records them.
+fn field_names() -> &'static [&'static str] {
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for FieldNames<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("expected a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only recording field names"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+            identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = GrafanaContextServerSettings::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// Merges the JSON file named by the `config_file` key underneath the
+/// other top-level settings.
+///
+/// This lets a shared baseline be kept in its own file, with individual
+/// settings overriding it. The key itself is removed from the returned
+/// settings, and may not appear in the file.
+///
+/// The file has to be named, since the extension API gives context
+/// servers the IDs of the project's worktrees but not their paths.
+pub(crate) fn merge_config_file(
+    value: serde_json::Value,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<serde_json::Value> {
+    let serde_json::Value::Object(mut settings) = value else {
+        return Err("invalid Grafana settings: expected an object".into());
+    };
+    let mut path = match settings.remove("config_file") {
+        None | Some(serde_json::Value::Null) => return Ok(settings.into()),
+        Some(path @ serde_json::Value::String(_)) => path,
+        Some(_) => return Err("`config_file` must be a string".into()),
+    };
+    interpolate::interpolate(&mut path, lookup)?;
+    let path = path.as_str().unwrap_or_default();
+
+    paths::check_readable_file("config_file", path)?;
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read `config_file` '{path}': {err}"))?;
+    let file: serde_json::Value = serde_json::from_str(&contents)
+        .map_err(|err| format!("failed to parse `config_file` '{path}': {err}"))?;
+    let serde_json::Value::Object(mut merged) = file else {
+        return Err(format!("`config_file` '{path}' must contain a JSON object"));
+    };
+    if merged.contains_key("config_file") {
+        return Err(format!(
+            "`config_file` '{path}' must not itself contain `config_file`"
+        ));
+    }
+    merged.extend(settings);
+    Ok(merged.into())
+}
+
+/// The keys which select a profile, and the keys of the maps they select
+/// from. `instance`/`instances` are alternative names for
+/// `profile`/`profiles`.
+const PROFILE_KEYS: [(&str, &str); 2] = [("profile", "profiles"), ("instance", "instances")];
+
+/// Merges the profile selected by the `profile` key from the `profiles`
+/// map underneath the other top-level settings.
+///
+/// Top-level settings take precedence over those in the profile. Both keys
+/// are removed from the returned settings. `instance` and `instances` may
+/// be used instead, but not together with `profile` and `profiles`.
+///
+/// If `instance_from_env` names an environment variable which `lookup`
+/// finds with a non-empty value, that value selects the profile instead.
+///
+/// Also returns which profile was selected and how, such as "profile
+/// 'prod'", if one was.
+pub(crate) fn apply_profile(
+    value: serde_json::Value,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<(serde_json::Value, Option<String>)> {
+    let serde_json::Value::Object(mut settings) = value else {
+        return Err("invalid Grafana settings: expected an object".into());
+    };
+    let from_env = match settings.remove("instance_from_env") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(serde_json::Value::String(var)) => lookup(&var)
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| (var, name.trim().to_string())),
+        Some(_) => return Err("`instance_from_env` must be the name of a variable".into()),
+    };
+    let used: Vec<(&str, &str)> = PROFILE_KEYS
+        .into_iter()
+        .filter(|(key, map)| settings.contains_key(*key) || settings.contains_key(*map))
+        .collect();
+    let (key, map) = match used.as_slice() {
+        [] if from_env.is_none() => return Ok((settings.into(), None)),
+        [] => PROFILE_KEYS[1],
+        [keys] => *keys,
+        _ => {
+            return Err(
+                "`instance`/`instances` are alternative names for `profile`/`profiles`; use \
+                 only one pair"
+                    .into(),
+            );
+        }
+    };
+
+    let profiles = match settings.remove(map) {
+        None => BTreeMap::new(),
+        Some(profiles) => {
+            serde_json::from_value::<BTreeMap<String, serde_json::Value>>(profiles)
+                .map_err(|_| format!("`{map}` must be an object mapping names to settings"))?
+        }
+    };
+    let (selected, selection) = match (settings.remove(key), from_env) {
+        (_, Some((var, name))) => {
+            let selection = format!("{key} '{name}' selected by {var}");
+            (name, selection)
+        }
+        (None | Some(serde_json::Value::Null), None) => return Ok((settings.into(), None)),
+        (Some(serde_json::Value::String(name)), None) => {
+            let selection = format!("{key} '{name}'");
+            (name, selection)
+        }
+        (Some(_), None) => return Err(format!("`{key}` must be a string")),
+    };
+
+    let Some(profile) = profiles.get(&selected) else {
+        let available = if profiles.is_empty() {
+            format!("no {map} are defined")
+        } else {
+            format!(
+                "available {map}: {}",
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        };
+        return Err(format!("unknown {key} '{selected}'; {available}"));
+    };
+    let serde_json::Value::Object(profile) = profile else {
+        return Err(format!("{key} '{selected}' must be an object"));
+    };
+    if PROFILE_KEYS
+        .iter()
+        .any(|(key, map)| profile.contains_key(*key) || profile.contains_key(*map))
+        || profile.contains_key("instance_from_env")
+    {
+        return Err(format!(
+            "{key} '{selected}' must not contain `{key}`, `{map}` or `instance_from_env`"
+        ));
+    }
+
+    let mut merged = profile.clone();
+    merged.extend(settings);
+    Ok((merged.into(), Some(selection)))
+}
+
+#[cfg(test)]
+mod tests {
+    use zed_extension_api::serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn errors_never_contain_tokens() {
+        let token = "glsa_never_leaked";
+        let settings = [
+            json!({ "grafana_service_account_token": token, "org_id": token }),
+            json!({ "grafana_service_account_token": token, "org_id": "one" }),
+            json!({ "grafana_service_account_token": token, "timeout_seconds": token }),
+            json!({ "grafana_password": token, "keep_versions": -1 }),
+        ];
+        for settings in settings {
+            let err = parse(settings.clone()).expect_err("invalid settings");
+            assert!(!err.contains(token), "{settings} leaked the token: {err}");
+        }
+    }
+
+    #[test]
+    fn settings_take_precedence_by_default() {
+        let settings = parse(json!({})).unwrap();
+        assert_eq!(settings.settings_precedence, Precedence::Settings);
+        let settings = parse(json!({ "settings_precedence": "env" })).unwrap();
+        assert_eq!(settings.settings_precedence, Precedence::Env);
+        assert!(parse(json!({ "settings_precedence": "shell" })).is_err());
+    }
+
+    fn profile(value: serde_json::Value, env: &[(&str, &str)]) -> Result<serde_json::Value> {
+        let lookup = |name: &str| {
+            env.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        };
+        apply_profile(value, &lookup).map(|(value, _)| value)
+    }
+
+    #[test]
+    fn top_level_settings_override_the_profile() {
+        let settings = json!({
+            "profile": "prod",
+            "org_id": 3,
+            "profiles": {
+                "prod": { "grafana_url": "https://prod.example.com", "org_id": 2 },
+                "dev": { "grafana_url": "http://localhost:3000" },
+            },
+        });
+        assert_eq!(
+            profile(settings, &[]).unwrap(),
+            json!({ "grafana_url": "https://prod.example.com", "org_id": 3 })
+        );
+    }
+
+    #[test]
+    fn profiles_are_optional() {
+        let settings = json!({ "grafana_url": "https://grafana.example.com" });
+        assert_eq!(profile(settings.clone(), &[]).unwrap(), settings);
+        let unused = json!({ "profiles": { "dev": { "org_id": 2 } } });
+        assert_eq!(profile(unused, &[]).unwrap(), json!({}));
+    }
+
+    #[test]
+    fn instance_from_env_selects_the_instance() {
+        let settings = json!({
+            "instance": "dev",
+            "instance_from_env": "GRAFANA_INSTANCE",
+            "instances": { "dev": { "org_id": 1 }, "prod": { "org_id": 2 } },
+        });
+        assert_eq!(
+            profile(settings.clone(), &[("GRAFANA_INSTANCE", "prod")]).unwrap(),
+            json!({ "org_id": 2 })
+        );
+        assert_eq!(
+            profile(settings, &[("GRAFANA_INSTANCE", " ")]).unwrap(),
+            json!({ "org_id": 1 })
+        );
+    }
+
+    #[test]
+    fn rejects_missing_and_ambiguous_profiles() {
+        let cases = [
+            (
+                json!({ "profile": "staging", "profiles": { "dev": {}, "prod": {} } }),
+                "unknown profile 'staging'; available profiles: dev, prod",
+            ),
+            (
+                json!({ "profile": "dev" }),
+                "unknown profile 'dev'; no profiles are defined",
+            ),
+            (
+                json!({ "profile": "dev", "instance": "dev" }),
+                "`instance`/`instances` are alternative names for `profile`/`profiles`; use \
+                 only one pair",
+            ),
+            (
+                json!({ "profile": "dev", "profiles": { "dev": { "profile": "prod" } } }),
+                "profile 'dev' must not contain `profile`, `profiles` or `instance_from_env`",
+            ),
+            (
+                json!({ "profile": 1, "profiles": {} }),
+                "`profile` must be a string",
+            ),
+        ];
+        for (settings, expected) in cases {
+            assert_eq!(
+                profile(settings.clone(), &[]).unwrap_err(),
+                expected,
+                "{settings}"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected_in_strict_mode() {
+        let cases = [
+            ("grafana-url", "`grafana-url` (did you mean `grafana_url`?)"),
+            ("grafanaUrl", "`grafanaUrl` (did you mean `grafana_url`?)"),
+            ("orgId", "`orgId` (did you mean `org_id`?)"),
+            (
+                "enabled_tool",
+                "`enabled_tool` (did you mean `enabled_tools`?)",
+            ),
+            ("nothing_like_a_setting", "`nothing_like_a_setting`"),
+        ];
+        for (key, expected) in cases {
+            let mut settings = json!({ "strict": true });
+            settings[key] = json!("value");
+            assert_eq!(
+                parse(settings.clone()).unwrap_err(),
+                format!("unknown Grafana settings: {expected}"),
+                "{key}"
+            );
+            settings["strict"] = json!(false);
+            assert!(parse(settings).is_ok(), "{key}");
+        }
+    }
+
+    #[test]
+    fn field_names_lists_every_setting() {
+        let fields = field_names();
+        for field in [
+            "grafana_url",
+            "strict",
+            "enabled_tools",
+            "remote_url",
+            "transport",
+        ] {
+            assert!(fields.contains(&field), "{field}");
+        }
+        assert!(!fields.contains(&"grafana_api_key"));
+    }
+
+    #[test]
+    fn aliases_move_to_their_replacements() {
+        let cases = [
+            (
+                json!({ "grafana_api_key": "glsa_a" }),
+                json!({ "grafana_service_account_token": "glsa_a" }),
+            ),
+            (
+                json!({ "grafana_api_key": "glsa_a", "grafana_service_account_token": "glsa_a" }),
+                json!({ "grafana_service_account_token": "glsa_a" }),
+            ),
+            (json!({ "debug": true }), json!({ "log_level": "debug" })),
+            (json!({ "debug": false }), json!({})),
+            (json!({ "debug": null }), json!({})),
+            (
+                json!({ "debug": true, "log_level": "warn" }),
+                json!({ "log_level": "warn" }),
+            ),
+        ];
+        for (settings, expected) in cases {
+            assert_eq!(
+                apply_aliases(settings.clone()).unwrap(),
+                expected,
+                "{settings}"
+            );
+        }
+    }
+
+    #[test]
+    fn conflicting_aliases_are_rejected() {
+        let settings = json!({
+            "grafana_api_key": "glsa_a",
+            "grafana_service_account_token": "glsa_b",
+        });
+        assert_eq!(
+            apply_aliases(settings).unwrap_err(),
+            "both `grafana_api_key` and `grafana_service_account_token` are set to different \
+             values; remove the deprecated `grafana_api_key`"
+        );
+        assert_eq!(
+            apply_aliases(json!({ "debug": "yes" })).unwrap_err(),
+            "`debug` must be a boolean"
+        );
+    }
+
+    #[test]
+    fn every_alias_replacement_is_a_setting() {
+        let fields = field_names();
+        for alias in ALIASES {
+            assert!(fields.contains(&alias.new), "{}", alias.new);
+            assert!(!fields.contains(&alias.old), "{}", alias.old);
+        }
+    }
+
+    /// Writes `contents` to a `config_file` named after `test`, returning
+    /// its path.
+    fn config_file(test: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("zed-mcp-grafana-{test}.json"));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn zed_settings_override_the_config_file() {
+        let path = config_file(
+            "config-file-precedence",
+            r#"{ "grafana_url": "https://shared.example.com", "org_id": 2, "read_only": true }"#,
+        );
+        let settings = parse(json!({
+            "config_file": path,
+            "grafana_url": "https://mine.example.com",
+        }))
+        .unwrap();
+        assert_eq!(
+            settings.grafana_url.as_deref(),
+            Some("https://mine.example.com")
+        );
+        assert_eq!(settings.org_id, Some(2));
+        assert!(settings.read_only);
+
+        // Both override the environment, unless `settings_precedence` says
+        // otherwise.
+        let env = Some("https://env.example.com".to_string());
+        let settings = parse(json!({ "config_file": path })).unwrap();
+        assert_eq!(
+            settings
+                .settings_precedence
+                .pick(settings.grafana_url, env.clone()),
+            Some("https://shared.example.com".into())
+        );
+        let settings = parse(json!({ "config_file": path, "settings_precedence": "env" })).unwrap();
+        assert_eq!(
+            settings.settings_precedence.pick(settings.grafana_url, env),
+            Some("https://env.example.com".into())
+        );
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn config_file_errors_name_the_file_and_location() {
+        let path = config_file("config-file-malformed", "{\n  \"grafana_url\": ,\n}");
+        let err = parse(json!({ "config_file": path })).unwrap_err();
+        assert!(
+            err.starts_with(&format!("failed to parse `config_file` '{path}'")),
+            "{err}"
+        );
+        assert!(err.ends_with("line 2 column 18"), "{err}");
+
+        let path = config_file(
+            "config-file-nested",
+            r#"{ "config_file": "/etc/other.json" }"#,
+        );
+        let err = parse(json!({ "config_file": path })).unwrap_err();
+        assert!(
+            err.contains("must not itself contain `config_file`"),
+            "{err}"
+        );
+        fs::remove_file(path).ok();
+
+        let err = parse(json!({ "config_file": ".zed/mcp-grafana.json" })).unwrap_err();
+        assert!(
+            err.starts_with("`config_file` must be an absolute path"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn transports_other_than_stdio_are_refused() {
+        assert!(parse(json!({ "transport": "stdio" })).is_ok());
+        let err = parse(json!({ "transport": "sse" })).unwrap_err();
+        assert!(err.starts_with("`transport` is \"sse\""), "{err}");
+    }
+
+    #[test]
+    fn the_selected_profile_is_described() {
+        let lookup = |name: &str| (name == "GRAFANA_INSTANCE").then(|| "prod".to_string());
+        let selection = |value| apply_profile(value, &lookup).map(|(_, selection)| selection);
+        let profiles = json!({ "prod": {}, "dev": {} });
+        assert_eq!(
+            selection(json!({ "profile": "dev", "profiles": profiles })),
+            Ok(Some("profile 'dev'".into()))
+        );
+        assert_eq!(
+            selection(json!({ "instance_from_env": "GRAFANA_INSTANCE", "instances": profiles })),
+            Ok(Some("instance 'prod' selected by GRAFANA_INSTANCE".into()))
+        );
+        assert_eq!(selection(json!({})), Ok(None));
+    }
+}
@@ -0,0 +1,64 @@
+//! Reporting the progress of installing the server.
+//!
+//! The extension API can only show the installation status of language
+//! servers, not of context servers, so the progress is written to Zed's log.
+
+use std::fmt;
+
+use crate::log;
+
+/// A phase of installing the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Status {
+    /// Looking up which release to install on GitHub.
+    CheckingForRelease,
+    /// Downloading `asset` of release `tag`, for `platform`.
+    Downloading {
+        tag: String,
+        asset: String,
+        platform: String,
+    },
+    /// Building release `tag` from source.
+    Building { tag: String },
+    /// Release `tag` was installed, with a binary of `size` bytes.
+    Installed { tag: String, size: u64 },
+    /// Installing the server failed with `error`.
+    Failed { error: String },
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CheckingForRelease => write!(f, "checking GitHub for the release to install"),
+            Self::Downloading {
+                tag,
+                asset,
+                platform,
+            } => write!(
+                f,
+                "downloading mcp-grafana {tag} ('{asset}') for {platform}"
+            ),
+            Self::Building { tag } => write!(f, "building mcp-grafana {tag} from source"),
+            Self::Installed { tag, size } => write!(
+                f,
+                "installed mcp-grafana {tag} ({:.1} MB)",
+                *size as f64 / 1_000_000.0
+            ),
+            Self::Failed { error } => write!(f, "failed to install the server: {error}"),
+        }
+    }
+}
+
+/// Receives the phases of installing the server as they happen.
+pub(crate) trait Reporter {
+    fn report(&self, status: Status);
+}
+
+/// Writes the phases of installing the server to Zed's log.
+pub(crate) struct LogReporter;
+
+impl Reporter for LogReporter {
+    fn report(&self, status: Status) {
+        log(status);
+    }
+}
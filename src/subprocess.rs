@@ -0,0 +1,74 @@
+//! Running helper processes with a timeout.
+
+use zed_extension_api::{self as zed, Result};
+
+/// How long looking up a program on `PATH` may take.
+const PATH_LOOKUP_TIMEOUT_SECS: u64 = 5;
+
+/// Runs `argv`, returning its output if it exits successfully.
+///
+/// On Unix the command is run under a `sh` watchdog which kills it after
+/// `timeout_secs`; Windows has no equivalent, so the command is run without
+/// a timeout there. `what` describes the command in error messages.
+pub(crate) fn run(
+    what: &str,
+    argv: Vec<String>,
+    timeout_secs: u64,
+) -> Result<zed::process::Output> {
+    let (platform, _) = zed::current_platform();
+    let description = argv.join(" ");
+
+    let mut process = match platform {
+        zed::Os::Windows => zed::process::Command::new(&argv[0]).args(&argv[1..]),
+        zed::Os::Mac | zed::Os::Linux => zed::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!(
+                r#""$@" & child=$!
+( sleep {timeout_secs}; kill "$child" ) >/dev/null 2>&1 & watchdog=$!
+wait "$child"; status=$?
+kill "$watchdog" 2>/dev/null
+exit $status"#
+            ))
+            .arg("sh")
+            .args(argv),
+    };
+    let output = process
+        .output()
+        .map_err(|err| format!("failed to run {what} '{description}': {err}"))?;
+
+    match output.status {
+        Some(0) => Ok(output),
+        // 143 is the exit status of a process killed by SIGTERM.
+        Some(143) if platform != zed::Os::Windows => Err(format!(
+            "{what} '{description}' timed out after {timeout_secs}s"
+        )),
+        status => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(format!(
+                "{what} '{description}' failed with {}: {}",
+                status.map_or("a signal".to_string(), |code| format!("exit code {code}")),
+                stderr.trim(),
+            ))
+        }
+    }
+}
+
+/// Returns the path of `program` on the `PATH` of the login shell, or on
+/// Windows of Zed itself.
+pub(crate) fn find_on_path(program: &str, platform: zed::Os) -> Option<String> {
+    let argv = match platform {
+        zed::Os::Windows => vec!["where".into(), format!("{program}.exe")],
+        zed::Os::Mac | zed::Os::Linux => vec![
+            "sh".into(),
+            "-c".into(),
+            format!(r#"exec "${{SHELL:-/bin/sh}}" -l -c 'command -v {program}' </dev/null"#),
+        ],
+    };
+    let output = run("PATH lookup", argv, PATH_LOOKUP_TIMEOUT_SECS).ok()?;
+    // The login shell may print other things first.
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .rfind(|line| line.ends_with(program) || line.ends_with(&format!("{program}.exe")))
+        .map(String::from)
+}
@@ -0,0 +1,104 @@
+//! "Did you mean" suggestions for misspelt names.
+
+/// Returns the candidate closest to `name`, if any is close enough to be a
+/// likely typo.
+///
+/// Case is ignored, and hyphens and camelCase are treated like the
+/// underscores of snake_case names.
+pub(crate) fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let name = to_snake_case(name);
+    let max_distance = (name.len() / 3).max(2);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Converts `name` from kebab-case or camelCase to snake_case.
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len());
+    for (i, c) in name.chars().enumerate() {
+        match c {
+            '-' | ' ' => snake.push('_'),
+            c if c.is_ascii_uppercase() => {
+                if i > 0 && !snake.ends_with('_') {
+                    snake.push('_');
+                }
+                snake.push(c.to_ascii_lowercase());
+            }
+            c => snake.push(c),
+        }
+    }
+    snake
+}
+
+/// Returns the Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distances() {
+        let cases = [
+            ("", "", 0),
+            ("abc", "", 3),
+            ("", "abc", 3),
+            ("kitten", "sitting", 3),
+            ("org_id", "org_id", 0),
+            ("org_di", "org_id", 2),
+            ("grafana_ur", "grafana_url", 1),
+            ("grafa_url", "grafana_url", 2),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(edit_distance(a, b), expected, "'{a}' to '{b}'");
+            assert_eq!(edit_distance(b, a), expected, "'{b}' to '{a}'");
+        }
+    }
+
+    #[test]
+    fn snake_cases_names() {
+        let cases = [
+            ("grafana-url", "grafana_url"),
+            ("grafanaUrl", "grafana_url"),
+            ("api key file", "api_key_file"),
+            ("Org-Id", "org_id"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(to_snake_case(name), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn suggests_likely_typos_only() {
+        let candidates = ["grafana_url", "org_id", "api_key_file", "enabled_tools"];
+        let cases = [
+            ("grafana-url", Some("grafana_url")),
+            ("grafanaUrl", Some("grafana_url")),
+            ("grafana_ulr", Some("grafana_url")),
+            ("orgId", Some("org_id")),
+            ("apiKeyFile", Some("api_key_file")),
+            ("enable_tools", Some("enabled_tools")),
+            ("completely_different", None),
+            ("x", None),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(closest(name, &candidates), expected, "{name}");
+        }
+    }
+}
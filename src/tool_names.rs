@@ -0,0 +1,253 @@
+//! Selection of individual tools by name, refining the selected
+//! categories.
+
+use zed_extension_api::Result;
+
+use crate::{log, server::Server, suggest};
+
+/// The tools known to the server, along with their categories.
+///
+/// Like [`tools::CATEGORIES`](crate::tools::CATEGORIES), this goes stale as
+/// the server adds tools, so unknown names are only logged.
+pub(crate) const TOOLS: &[(&str, &str)] = &[
+    ("search_dashboards", "search"),
+    ("list_datasources", "datasource"),
+    ("get_datasource_by_uid", "datasource"),
+    ("get_datasource_by_name", "datasource"),
+    ("list_incidents", "incident"),
+    ("create_incident", "incident"),
+    ("add_activity_to_incident", "incident"),
+    ("get_incident", "incident"),
+    ("query_prometheus", "prometheus"),
+    ("list_prometheus_metric_metadata", "prometheus"),
+    ("list_prometheus_metric_names", "prometheus"),
+    ("list_prometheus_label_names", "prometheus"),
+    ("list_prometheus_label_values", "prometheus"),
+    ("query_loki_logs", "loki"),
+    ("query_loki_stats", "loki"),
+    ("list_loki_label_names", "loki"),
+    ("list_loki_label_values", "loki"),
+    ("list_alert_rules", "alerting"),
+    ("get_alert_rule_by_uid", "alerting"),
+    ("list_contact_points", "alerting"),
+    ("get_dashboard_by_uid", "dashboard"),
+    ("update_dashboard", "dashboard"),
+    ("get_dashboard_panel_queries", "dashboard"),
+    ("create_folder", "folder"),
+    ("list_oncall_schedules", "oncall"),
+    ("get_oncall_shift", "oncall"),
+    ("get_current_oncall_users", "oncall"),
+    ("list_oncall_teams", "oncall"),
+    ("list_oncall_users", "oncall"),
+    ("get_assertions", "asserts"),
+    ("get_sift_investigation", "sift"),
+    ("get_sift_analysis", "sift"),
+    ("list_sift_investigations", "sift"),
+    ("find_error_pattern_logs", "sift"),
+    ("find_slow_requests", "sift"),
+    ("list_teams", "admin"),
+    ("list_users_by_org", "admin"),
+    ("list_pyroscope_label_names", "pyroscope"),
+    ("list_pyroscope_label_values", "pyroscope"),
+    ("list_pyroscope_profile_types", "pyroscope"),
+    ("fetch_pyroscope_profile", "pyroscope"),
+    ("generate_deeplink", "navigation"),
+];
+
+/// Returns the server arguments selecting individual tools by name.
+///
+/// Entries may be names or simple globs, where `*` matches any run of
+/// characters, such as `list_*`. Globs are expanded against [`TOOLS`];
+/// names the extension doesn't know are logged and passed on unchanged.
+///
+/// Names refine categories: only tools in enabled categories can be
+/// enabled by name, so naming a tool whose category is disabled is an
+/// error, as is naming a tool in both lists. `category_enabled` says
+/// whether the category settings leave a category enabled.
+pub(crate) fn args(
+    enabled: &[String],
+    disabled: &[String],
+    category_enabled: impl Fn(&str) -> bool,
+    server: &mut Server,
+) -> Result<Vec<String>> {
+    let enabled = expand("enabled_tool_names", enabled)?;
+    let disabled = expand("disabled_tool_names", disabled)?;
+
+    let conflicts: Vec<&str> = disabled
+        .iter()
+        .filter(|name| enabled.contains(name))
+        .map(String::as_str)
+        .collect();
+    if !conflicts.is_empty() {
+        return Err(format!(
+            "tools are both enabled and disabled: {}; remove them from either \
+             `enabled_tool_names` or `disabled_tool_names`",
+            conflicts.join(", ")
+        ));
+    }
+    for name in &enabled {
+        if let Some(category) = category_of(name)
+            && !category_enabled(category)
+        {
+            return Err(format!(
+                "`enabled_tool_names` contains '{name}', but its category '{category}' is \
+                 disabled; enable the category as well"
+            ));
+        }
+    }
+
+    let mut args = vec![];
+    for (setting, flag, names) in [
+        ("enabled_tool_names", "enabled-tool-names", &enabled),
+        ("disabled_tool_names", "disabled-tool-names", &disabled),
+    ] {
+        if names.is_empty() {
+            continue;
+        }
+        server.require_flag(setting, flag)?;
+        args.extend([format!("--{flag}"), names.join(",")]);
+    }
+    Ok(args)
+}
+
+/// Expands the globs among `patterns`, given in `setting`, dropping
+/// duplicates.
+fn expand(setting: &str, patterns: &[String]) -> Result<Vec<String>> {
+    let mut names: Vec<String> = vec![];
+    let mut push = |name: &str| {
+        if !names.iter().any(|existing| existing == name) {
+            names.push(name.into());
+        }
+    };
+    for pattern in patterns {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            continue;
+        }
+        if !pattern
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '*')
+        {
+            return Err(format!(
+                "invalid tool name '{pattern}' in `{setting}`; expected a name such as \
+                 'query_prometheus' or a glob such as 'list_*'"
+            ));
+        }
+        if pattern.contains('*') {
+            let matches: Vec<&str> = TOOLS
+                .iter()
+                .map(|(name, _)| *name)
+                .filter(|name| glob_matches(pattern, name))
+                .collect();
+            if matches.is_empty() {
+                log(format!(
+                    "'{pattern}' in `{setting}` matches none of the tools the extension knows about"
+                ));
+            }
+            matches.into_iter().for_each(&mut push);
+        } else {
+            if category_of(pattern).is_none() {
+                let tools: Vec<&str> = TOOLS.iter().map(|(name, _)| *name).collect();
+                let suggestion = match suggest::closest(pattern, &tools) {
+                    Some(name) => format!("; did you mean '{name}'?"),
+                    None => String::new(),
+                };
+                log(format!(
+                    "unknown tool '{pattern}' in `{setting}`, passing it to the server \
+                     anyway{suggestion}"
+                ));
+            }
+            push(pattern);
+        }
+    }
+    Ok(names)
+}
+
+fn category_of(name: &str) -> Option<&'static str> {
+    TOOLS
+        .iter()
+        .find(|(tool, _)| *tool == name)
+        .map(|(_, category)| *category)
+}
+
+/// Returns whether `name` matches `pattern`, in which `*` matches any run
+/// of characters.
+pub(crate) fn glob_matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_runs_of_characters() {
+        let cases = [
+            ("list_*", "list_teams", true),
+            ("list_*", "list_", true),
+            ("list_*", "get_list_teams", false),
+            ("*_loki_*", "query_loki_logs", true),
+            ("*_loki_*", "loki_logs", false),
+            ("*", "anything", true),
+            ("*", "", true),
+            ("query_prometheus", "query_prometheus", true),
+            ("query_prometheus", "query_prometheus_range", false),
+            ("a*a", "a", false),
+            ("a*a", "aa", true),
+            ("list_*_names", "list_loki_label_names", true),
+            ("list_*_names", "list_loki_label_values", false),
+            ("**", "x", true),
+        ];
+        for (pattern, name, expected) in cases {
+            assert_eq!(
+                glob_matches(pattern, name),
+                expected,
+                "'{pattern}' against '{name}'"
+            );
+        }
+    }
+
+    #[test]
+    fn expands_globs_against_the_known_tools() {
+        let patterns = ["list_oncall_*", "get_oncall_shift", "list_oncall_teams"].map(String::from);
+        assert_eq!(
+            expand("enabled_tool_names", &patterns),
+            Ok(vec![
+                "list_oncall_schedules".to_string(),
+                "list_oncall_teams".to_string(),
+                "list_oncall_users".to_string(),
+                "get_oncall_shift".to_string(),
+            ])
+        );
+        assert_eq!(
+            expand("enabled_tool_names", &["new_tool".into(), " ".into()]),
+            Ok(vec!["new_tool".to_string()])
+        );
+        assert!(expand("disabled_tool_names", &["list-teams".into()]).is_err());
+    }
+
+    #[test]
+    fn every_tool_has_a_known_category() {
+        for (tool, category) in TOOLS {
+            assert!(
+                crate::tools::CATEGORIES.contains(category),
+                "{tool} is in unknown category '{category}'"
+            );
+        }
+    }
+}
@@ -0,0 +1,458 @@
+//! Selection of the tool categories exposed by the server.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserializer, de};
+use zed_extension_api::Result;
+
+use crate::{log, suggest};
+
+/// The tool categories known to the server, which can each be disabled
+/// with a `--disable-<category>` flag.
+///
+/// Update this as the server adds categories; `allow_unknown_tools` covers
+/// the gap in the meantime.
+pub(crate) const CATEGORIES: &[&str] = &[
+    "search",
+    "datasource",
+    "incident",
+    "prometheus",
+    "loki",
+    "alerting",
+    "dashboard",
+    "folder",
+    "oncall",
+    "asserts",
+    "sift",
+    "admin",
+    "pyroscope",
+    "navigation",
+];
+
+/// Returns the server arguments selecting the tools to expose.
+///
+/// `toggles` is the structured `tools` block, which maps categories to
+/// whether they are enabled and can't be combined with the lists. Empty
+/// lists and blocks are treated as unset. Entries of `enabled` prefixed
+/// with `!` enable every category except those, and are translated into
+/// `--disable-<category>` flags. It is an error for a category to
+/// be both enabled and disabled, or for a category to be unknown unless
+/// `allow_unknown` is set, in which case unknown categories are only
+/// logged.
+pub(crate) fn args(
+    enabled: Option<&[String]>,
+    disabled: Option<&[String]>,
+    toggles: Option<&BTreeMap<String, bool>>,
+    allow_unknown: bool,
+) -> Result<Vec<String>> {
+    let enabled = enabled.filter(|tools| !tools.is_empty());
+    let disabled = disabled.filter(|tools| !tools.is_empty());
+
+    if let Some(toggles) = toggles.filter(|toggles| !toggles.is_empty()) {
+        if enabled.is_some() || disabled.is_some() {
+            return Err(
+                "the `tools` block cannot be combined with `enabled_tools` or `disabled_tools`; \
+                 use only one of them"
+                    .into(),
+            );
+        }
+        return toggle_args(toggles, allow_unknown);
+    }
+
+    if let Some(negated) = enabled.map(negated).transpose()?.flatten() {
+        let disabled = disabled.into_iter().flatten().map(String::as_str);
+        let mut args = vec![];
+        for (setting, tool) in negated
+            .into_iter()
+            .map(|tool| ("enabled_tools", tool))
+            .chain(disabled.map(|tool| ("disabled_tools", tool)))
+        {
+            check_known(setting, tool, allow_unknown)?;
+            let flag = format!("--disable-{tool}");
+            if !args.contains(&flag) {
+                args.push(flag);
+            }
+        }
+        return Ok(args);
+    }
+
+    if let (Some(enabled), Some(disabled)) = (enabled, disabled) {
+        let conflicts: Vec<&str> = disabled
+            .iter()
+            .filter(|tool| enabled.contains(tool))
+            .map(String::as_str)
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(format!(
+                "tool categories are both enabled and disabled: {}; remove them from either \
+                 `enabled_tools` or `disabled_tools`",
+                conflicts.join(", ")
+            ));
+        }
+    }
+
+    let mut args = vec![];
+    if let Some(enabled) = enabled {
+        for tool in enabled {
+            check_known("enabled_tools", tool, allow_unknown)?;
+        }
+        args.push("--enabled-tools".into());
+        args.push(enabled.join(","));
+    }
+    for tool in disabled.into_iter().flatten() {
+        check_known("disabled_tools", tool, allow_unknown)?;
+        args.push(format!("--disable-{tool}"));
+    }
+    Ok(args)
+}
+
+/// Returns the categories negated with `!` in `enabled_tools`, which
+/// means every category except those, or `None` if no entry is negated.
+///
+/// Mixing negated and plain entries is an error, since it's unclear
+/// whether the plain ones are meant to be the only enabled categories.
+fn negated(enabled: &[String]) -> Result<Option<Vec<&str>>> {
+    let negated: Vec<&str> = enabled
+        .iter()
+        .filter_map(|tool| tool.strip_prefix('!'))
+        .map(str::trim)
+        .collect();
+    if negated.is_empty() {
+        return Ok(None);
+    }
+    if negated.len() < enabled.len() {
+        return Err(
+            "`enabled_tools` mixes negated entries such as '!admin' with plain ones; list \
+             either the only categories to enable, or the categories to leave out prefixed \
+             with '!'"
+                .into(),
+        );
+    }
+    Ok(Some(negated))
+}
+
+/// Returns whether `category` stays enabled under the category settings
+/// given to [`args`].
+pub(crate) fn is_enabled(
+    category: &str,
+    enabled: Option<&[String]>,
+    disabled: Option<&[String]>,
+    toggles: Option<&BTreeMap<String, bool>>,
+) -> bool {
+    if let Some(toggles) = toggles.filter(|toggles| !toggles.is_empty()) {
+        return toggles.get(category) != Some(&false);
+    }
+    let listed = |tools: &[String]| tools.iter().any(|tool| tool == category);
+    if let Some(enabled) = enabled.filter(|tools| !tools.is_empty()) {
+        match negated(enabled) {
+            Ok(Some(negated)) if negated.contains(&category) => return false,
+            Ok(None) if !listed(enabled) => return false,
+            _ => {}
+        }
+    }
+    !disabled.is_some_and(listed)
+}
+
+/// Converts the `tools` block into server arguments.
+///
+/// Every category is enabled by default, so only categories set to
+/// `false` produce a `--disable-<category>` flag.
+fn toggle_args(toggles: &BTreeMap<String, bool>, allow_unknown: bool) -> Result<Vec<String>> {
+    let mut args = vec![];
+    for (tool, &enabled) in toggles {
+        check_known("tools", tool, allow_unknown)?;
+        if !enabled {
+            args.push(format!("--disable-{tool}"));
+        }
+    }
+    Ok(args)
+}
+
+/// Returns an error if `tool`, given in `setting`, isn't a known category,
+/// or just logs it if `allow_unknown` is set.
+fn check_known(setting: &str, tool: &str, allow_unknown: bool) -> Result<()> {
+    if CATEGORIES.contains(&tool) {
+        return Ok(());
+    }
+    let suggestion = match suggest::closest(tool, CATEGORIES) {
+        Some(category) => format!("did you mean '{category}'? "),
+        None => String::new(),
+    };
+    let message = format!(
+        "unknown tool category '{tool}' in `{setting}`; {suggestion}known categories are {}",
+        CATEGORIES.join(", ")
+    );
+    if allow_unknown {
+        log(message);
+        return Ok(());
+    }
+    Err(format!(
+        "{message}; set `allow_unknown_tools` to true if the server supports it"
+    ))
+}
+
+/// Deserializes a list of tool categories given either as an array of
+/// strings or as a comma-separated string, as in the server's own flags.
+///
+/// Whitespace around entries is trimmed and empty entries are dropped.
+pub(crate) fn deserialize_list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct ListVisitor;
+
+    impl<'de> de::Visitor<'de> for ListVisitor {
+        type Value = Option<Vec<String>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str(
+                "a list of tool categories, as an array of strings or a comma-separated string",
+            )
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(self)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            Ok(Some(normalize(value.split(','))))
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut tools = vec![];
+            while let Some(tool) = seq.next_element::<String>()? {
+                tools.push(tool);
+            }
+            Ok(Some(normalize(tools.iter().map(String::as_str))))
+        }
+    }
+
+    deserializer.deserialize_option(ListVisitor)
+}
+
+fn normalize<'a>(tools: impl Iterator<Item = &'a str>) -> Vec<String> {
+    tools
+        .map(str::trim)
+        .filter(|tool| !tool.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use zed_extension_api::serde_json::{self, json};
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct List {
+        #[serde(default, deserialize_with = "deserialize_list")]
+        tools: Option<Vec<String>>,
+    }
+
+    fn list(value: serde_json::Value) -> Result<Option<Vec<String>>, String> {
+        serde_json::from_value::<List>(value)
+            .map(|list| list.tools)
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn lists_are_arrays_or_comma_separated_strings() {
+        let expected = Some(vec!["loki".to_string(), "prometheus".to_string()]);
+        assert_eq!(
+            list(json!({ "tools": ["loki", "prometheus"] })),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            list(json!({ "tools": "loki,prometheus" })),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            list(json!({ "tools": " loki , ,prometheus, " })),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            list(json!({ "tools": [" loki", "", "prometheus "] })),
+            Ok(expected)
+        );
+        assert_eq!(list(json!({ "tools": "" })), Ok(Some(vec![])));
+        assert_eq!(list(json!({ "tools": null })), Ok(None));
+        assert_eq!(list(json!({})), Ok(None));
+    }
+
+    #[test]
+    fn lists_of_other_types_are_rejected() {
+        for value in [json!(3), json!({ "loki": true }), json!(true)] {
+            let err = list(json!({ "tools": value })).unwrap_err();
+            assert!(
+                err.contains(
+                    "a list of tool categories, as an array of strings or a comma-separated string"
+                ),
+                "{value}: {err}"
+            );
+        }
+        assert!(list(json!({ "tools": ["loki", 3] })).is_err());
+    }
+
+    fn strings(tools: &[&str]) -> Vec<String> {
+        tools.iter().map(|tool| tool.to_string()).collect()
+    }
+
+    fn toggles(toggles: &[(&str, bool)]) -> BTreeMap<String, bool> {
+        toggles
+            .iter()
+            .map(|(tool, enabled)| (tool.to_string(), *enabled))
+            .collect()
+    }
+
+    #[test]
+    fn toggles_disable_each_category_set_to_false() {
+        for category in CATEGORIES {
+            let block = toggles(&[(category, false)]);
+            assert_eq!(
+                args(None, None, Some(&block), false),
+                Ok(vec![format!("--disable-{category}")])
+            );
+            let block = toggles(&[(category, true)]);
+            assert_eq!(args(None, None, Some(&block), false), Ok(vec![]));
+            assert!(is_enabled(category, None, None, Some(&block)));
+        }
+        let block = toggles(&[("loki", false), ("admin", false), ("search", true)]);
+        assert_eq!(
+            args(None, None, Some(&block), false),
+            Ok(strings(&["--disable-admin", "--disable-loki"]))
+        );
+        assert!(!is_enabled("loki", None, None, Some(&block)));
+        assert!(is_enabled("prometheus", None, None, Some(&block)));
+    }
+
+    #[test]
+    fn toggles_cannot_be_combined_with_the_lists() {
+        let block = toggles(&[("loki", false)]);
+        let list = strings(&["admin"]);
+        for (enabled, disabled) in [(Some(&list[..]), None), (None, Some(&list[..]))] {
+            assert!(
+                args(enabled, disabled, Some(&block), false)
+                    .unwrap_err()
+                    .starts_with("the `tools` block cannot be combined")
+            );
+        }
+        assert_eq!(
+            args(None, Some(&list), Some(&BTreeMap::new()), false),
+            Ok(strings(&["--disable-admin"]))
+        );
+    }
+
+    #[test]
+    fn toggles_reject_unknown_categories() {
+        let block = toggles(&[("lokii", false)]);
+        let err = args(None, None, Some(&block), false).unwrap_err();
+        assert!(err.contains("unknown tool category 'lokii' in `tools`; did you mean 'loki'?"));
+        assert_eq!(
+            args(None, None, Some(&block), true),
+            Ok(strings(&["--disable-lokii"]))
+        );
+    }
+
+    #[test]
+    fn categories_are_unique_lowercase_flag_names() {
+        let mut seen = std::collections::BTreeSet::new();
+        for category in CATEGORIES {
+            assert!(seen.insert(category), "'{category}' is listed twice");
+            assert!(
+                category.chars().all(|c| c.is_ascii_lowercase() || c == '-'),
+                "'{category}' isn't usable in a --disable-{category} flag"
+            );
+        }
+    }
+
+    #[test]
+    fn lists_are_checked_against_the_known_categories() {
+        assert_eq!(
+            args(Some(&strings(&["loki", "prometheus"])), None, None, false),
+            Ok(strings(&["--enabled-tools", "loki,prometheus"]))
+        );
+        let err = args(Some(&strings(&["promethus"])), None, None, false).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "unknown tool category 'promethus' in `enabled_tools`; did you mean \
+                 'prometheus'? known categories are {}; set `allow_unknown_tools` to true if \
+                 the server supports it",
+                CATEGORIES.join(", ")
+            )
+        );
+        let err = args(None, Some(&strings(&["xyzzy"])), None, false).unwrap_err();
+        assert!(err.starts_with("unknown tool category 'xyzzy' in `disabled_tools`; known"));
+        assert_eq!(
+            args(None, Some(&strings(&["xyzzy"])), None, true),
+            Ok(strings(&["--disable-xyzzy"]))
+        );
+    }
+
+    #[test]
+    fn categories_cannot_be_both_enabled_and_disabled() {
+        let err = args(
+            Some(&strings(&["loki", "admin"])),
+            Some(&strings(&["admin"])),
+            None,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.starts_with("tool categories are both enabled and disabled: admin;"));
+    }
+
+    #[test]
+    fn negated_entries_enable_every_other_category() {
+        /// The enabled and disabled categories, and the expected flags.
+        type Case = (
+            &'static [&'static str],
+            Option<&'static [&'static str]>,
+            &'static [&'static str],
+        );
+        let cases: &[Case] = &[
+            (&["!admin"], None, &["--disable-admin"]),
+            (
+                &["!admin", "! oncall"],
+                None,
+                &["--disable-admin", "--disable-oncall"],
+            ),
+            (
+                &["!admin"],
+                Some(&["oncall", "admin"]),
+                &["--disable-admin", "--disable-oncall"],
+            ),
+        ];
+        for (enabled, disabled, expected) in cases {
+            let disabled = disabled.map(strings);
+            assert_eq!(
+                args(Some(&strings(enabled)), disabled.as_deref(), None, false),
+                Ok(strings(expected)),
+                "{enabled:?} {disabled:?}"
+            );
+        }
+        let enabled = strings(&["!admin"]);
+        assert!(!is_enabled("admin", Some(&enabled), None, None));
+        assert!(is_enabled("loki", Some(&enabled), None, None));
+    }
+
+    #[test]
+    fn negated_and_plain_entries_cannot_be_mixed() {
+        assert!(negated(&strings(&["loki", "!admin"])).is_err());
+        assert_eq!(negated(&strings(&["loki"])), Ok(None));
+        let err = args(Some(&strings(&["!admn"])), None, None, false).unwrap_err();
+        assert!(err.contains("'admn' in `enabled_tools`; did you mean 'admin'?"));
+    }
+}